@@ -0,0 +1,50 @@
+use clap::Parser;
+
+use crate::domain::PackageName;
+use crate::sources::{check_root_licenses, ensure_licensed, PackageRegistry};
+
+use super::{print_json, OutputFormat};
+
+/// Checks root packages and all of their dependencies against the license policy, without
+/// extracting anything. Waived violations are noted, any others fail the check.
+#[derive(Debug, Parser)]
+pub struct CheckCommand {
+    /// List of packages names to check. Defaults to every package exposed from `Packages/`.
+    #[arg(short, long)]
+    pub root_packages: Vec<String>,
+}
+
+impl CheckCommand {
+    pub fn run(
+        self,
+        package_registry: &PackageRegistry,
+        format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        let root_packages = if self.root_packages.is_empty() {
+            package_registry
+                .root_packages()
+                .map(|package| package.thunk_name.clone())
+                .collect()
+        } else {
+            self.root_packages
+                .iter()
+                .map(|i| PackageName(i.to_owned()))
+                .collect::<Vec<PackageName>>()
+        };
+
+        let report = check_root_licenses(package_registry, &root_packages)?;
+
+        // Print the full report first so the exit code can still signal failure
+        if format == OutputFormat::Json {
+            print_json(&report)?;
+        }
+
+        ensure_licensed(&report)?;
+
+        if format == OutputFormat::Text {
+            println!("\nAll packages are licensed");
+        }
+
+        Ok(())
+    }
+}
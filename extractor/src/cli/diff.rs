@@ -0,0 +1,202 @@
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use console::style;
+
+use crate::config::ExtractorConfig;
+use crate::domain::License;
+use crate::sources::{
+    diff_registries, ClientTrackerPackageSource, CorePackageSource, DirectoryPackageSource,
+    FileChange, ModelPackageSource, PackageDiff, RegistryDiff,
+};
+
+use super::{print_json, resolve_path, OutputFormat, SourceOptions};
+
+/// Compares two snapshots of CorePackages, e.g. from two Studio releases.
+#[derive(Debug, Parser)]
+pub struct DiffCommand {
+    /// Old snapshot: a `Packages/` directory or a model or place file. With
+    /// `--client-tracker`, a Client Tracker version instead.
+    pub old: String,
+
+    /// New snapshot, given the same way as the old one.
+    pub new: String,
+
+    /// Include a unified diff of every changed file.
+    #[arg(long, default_value_t = false)]
+    pub patch: bool,
+}
+
+impl DiffCommand {
+    pub fn run(
+        self,
+        source_options: &SourceOptions,
+        config: ExtractorConfig,
+        format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        if source_options.packages_path.is_some()
+            || source_options.model.is_some()
+            || source_options.studio_version.is_some()
+        {
+            bail!(
+                "diff takes both snapshots as arguments, --packages-path, --model and --studio-version can't be used"
+            );
+        }
+
+        // Sources have to outlive their registries, see `Options::run`
+        let old_source = open_snapshot(source_options, &self.old)?;
+        let new_source = open_snapshot(source_options, &self.new)?;
+
+        let old_registry = old_source
+            .load_registry(config.clone())
+            .context(format!("Failed to load CorePackages from {:?}", self.old))?;
+        let new_registry = new_source
+            .load_registry(config)
+            .context(format!("Failed to load CorePackages from {:?}", self.new))?;
+
+        // Packages only need to be in one of the snapshots, since either can be the odd one out
+        new_registry
+            .config()
+            .check_package_references(old_registry.index_names().chain(new_registry.index_names()))
+            .context("Invalid config")?;
+
+        let mut registry_diff = diff_registries(&old_registry, &new_registry)
+            .context("Failed to compare CorePackages")?;
+
+        if !self.patch {
+            for package_diff in &mut registry_diff.changed {
+                for file_diff in &mut package_diff.files {
+                    file_diff.patch = None;
+                }
+            }
+        }
+
+        match format {
+            OutputFormat::Text => print_diff(&registry_diff),
+            OutputFormat::Json => print_json(&registry_diff)?,
+        }
+
+        Ok(())
+    }
+}
+
+fn open_snapshot(
+    source_options: &SourceOptions,
+    snapshot: &str,
+) -> anyhow::Result<Box<dyn CorePackageSource>> {
+    if let Some(client_tracker) = &source_options.client_tracker {
+        return Ok(Box::new(ClientTrackerPackageSource::new(
+            resolve_path(client_tracker),
+            Some(snapshot.to_owned()),
+        )));
+    }
+
+    let path = resolve_path(Path::new(snapshot));
+
+    let source: Box<dyn CorePackageSource> = if path.is_dir() {
+        Box::new(DirectoryPackageSource::new(path))
+    } else {
+        Box::new(ModelPackageSource::open(&path).context("Failed to open model file")?)
+    };
+
+    Ok(source)
+}
+
+fn print_diff(registry_diff: &RegistryDiff) {
+    if registry_diff.is_empty() {
+        println!("No changes");
+        return;
+    }
+
+    for thunk_name in &registry_diff.added {
+        println!("{} {}", style("+").green(), style(&thunk_name.0).bold());
+    }
+
+    for thunk_name in &registry_diff.removed {
+        println!("{} {}", style("-").red(), style(&thunk_name.0).bold());
+    }
+
+    for package_diff in &registry_diff.changed {
+        print_package_diff(package_diff);
+    }
+
+    println!(
+        "\n{} added, {} removed, {} changed",
+        registry_diff.added.len(),
+        registry_diff.removed.len(),
+        registry_diff.changed.len(),
+    );
+}
+
+fn print_package_diff(package_diff: &PackageDiff) {
+    let old_thunk_name = &package_diff.old_thunk_name.0;
+    let new_thunk_name = &package_diff.new_thunk_name.0;
+
+    if old_thunk_name == new_thunk_name {
+        println!("{} {}", style("~").yellow(), style(new_thunk_name).bold());
+    } else {
+        println!(
+            "{} {} (was {old_thunk_name})",
+            style("~").yellow(),
+            style(new_thunk_name).bold()
+        );
+    }
+
+    if let Some(version) = &package_diff.version {
+        println!("    version: {} -> {}", version.old, version.new);
+    }
+
+    if let Some(licenses) = &package_diff.licenses {
+        println!(
+            "    licenses: {} -> {}",
+            join_licenses(&licenses.old),
+            join_licenses(&licenses.new)
+        );
+    }
+
+    for dependency in &package_diff.added_dependencies {
+        println!("    {} dependency {dependency}", style("+").green());
+    }
+
+    for dependency in &package_diff.removed_dependencies {
+        println!("    {} dependency {dependency}", style("-").red());
+    }
+
+    for file_diff in &package_diff.files {
+        let marker = match file_diff.change {
+            FileChange::Added => style("+").green(),
+            FileChange::Removed => style("-").red(),
+            FileChange::Modified => style("~").yellow(),
+        };
+
+        println!(
+            "    {marker} {} (+{} -{})",
+            file_diff.path.display(),
+            file_diff.insertions,
+            file_diff.deletions
+        );
+
+        if let Some(patch) = &file_diff.patch {
+            for line in patch.lines() {
+                let line = if line.starts_with('+') {
+                    style(line).green()
+                } else if line.starts_with('-') {
+                    style(line).red()
+                } else {
+                    style(line).dim()
+                };
+
+                println!("        {line}");
+            }
+        }
+    }
+}
+
+fn join_licenses(licenses: &[License]) -> String {
+    licenses
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
@@ -0,0 +1,192 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::{Parser, ValueEnum};
+use console::style;
+
+use crate::domain::PackageName;
+use crate::sources::{
+    check_root_licenses, ensure_licensed, extract_packages, plan_extraction, update_packages,
+    PackagePlan, PackageRegistry, PackageSync, SbomFormat, SourceKind,
+};
+
+use super::{print_json, resolve_path, OutputFormat};
+
+/// Extracts root packages and all of their dependencies into a directory, structured for Wally.
+#[derive(Debug, Parser)]
+pub struct ExtractCommand {
+    /// List of packages names to search for first.
+    #[arg(short, long, required = true)]
+    pub root_packages: Vec<String>,
+
+    #[arg(short, long, required = true)]
+    /// Directory to write all extracted packages.
+    pub export_to: PathBuf,
+
+    /// Print what would be written instead of writing it. Generated manifests, replaced and
+    /// allowed files are printed in full.
+    #[arg(long, default_value_t = false, conflicts_with = "update")]
+    pub dry_run: bool,
+
+    /// Sync into an existing output directory instead of failing when packages already exist.
+    /// Only files that changed upstream are rewritten or deleted, and packages extracted under an
+    /// older folder name are moved to their current folder.
+    #[arg(long, default_value_t = false)]
+    pub update: bool,
+
+    /// Also write a software bill of materials covering every extracted package to the output
+    /// directory. Can be given more than once.
+    #[arg(long, value_enum, conflicts_with = "dry_run")]
+    pub sbom: Vec<SbomFormatArg>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SbomFormatArg {
+    /// SPDX 2.3 JSON, written to `sbom.spdx.json`.
+    Spdx,
+    /// CycloneDX 1.5 JSON, written to `sbom.cdx.json`.
+    CycloneDx,
+}
+
+impl From<SbomFormatArg> for SbomFormat {
+    fn from(format: SbomFormatArg) -> Self {
+        match format {
+            SbomFormatArg::Spdx => SbomFormat::Spdx,
+            SbomFormatArg::CycloneDx => SbomFormat::CycloneDx,
+        }
+    }
+}
+
+impl ExtractCommand {
+    pub fn run(
+        self,
+        package_registry: &PackageRegistry,
+        format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        let path = resolve_path(&self.export_to);
+
+        let root_packages = self
+            .root_packages
+            .iter()
+            .map(|i| PackageName(i.to_owned()))
+            .collect::<Vec<PackageName>>();
+
+        let sboms = self
+            .sbom
+            .iter()
+            .map(|&format| SbomFormat::from(format))
+            .collect::<Vec<SbomFormat>>();
+
+        // Exceptions to the license policy are waived in the config, never skipped here
+        let report = check_root_licenses(package_registry, &root_packages)?;
+        ensure_licensed(&report)?;
+
+        if self.dry_run {
+            let plans = plan_extraction(package_registry, &root_packages)
+                .context("Failed to plan extraction")?;
+
+            match format {
+                OutputFormat::Text => print_plans(&plans, &path),
+                OutputFormat::Json => print_json(&plans)?,
+            }
+
+            return Ok(());
+        }
+
+        if self.update {
+            let syncs = update_packages(package_registry, &root_packages, &path, &sboms)
+                .context("Failed to update CorePackages")?;
+
+            match format {
+                OutputFormat::Text => print_syncs(&syncs),
+                OutputFormat::Json => print_json(&syncs)?,
+            }
+
+            return Ok(());
+        }
+
+        let written_packages = extract_packages(package_registry, &root_packages, &path, &sboms)
+            .context("Failed to extract CorePackages")?;
+
+        if format == OutputFormat::Json {
+            print_json(&written_packages)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn print_plans(plans: &[PackagePlan], path: &Path) {
+    println!("Would write {} packages to {path:?}", plans.len());
+
+    for plan in plans {
+        println!("\n{}/", style(plan.folder.display()).bold().cyan());
+
+        for (relative_path, file) in &plan.files {
+            let lines = file.contents.lines().count();
+
+            let note = match file.kind {
+                SourceKind::Generated => "generated",
+                SourceKind::Original => "original",
+                SourceKind::Replaced => "replaced",
+                SourceKind::Allowed => "allowed module",
+            };
+
+            println!("  {} ({lines} lines, {note})", relative_path.display());
+
+            // Only print what differs from the upstream source
+            if file.kind != SourceKind::Original {
+                for line in file.contents.lines() {
+                    println!("    {}", style(line).dim());
+                }
+            }
+        }
+    }
+}
+
+fn print_syncs(syncs: &[PackageSync]) {
+    println!();
+
+    for sync in syncs {
+        if sync.is_unchanged() {
+            continue;
+        }
+
+        if sync.removed {
+            println!(
+                "{} (no longer extracted, removed)",
+                style(&sync.thunk_name.0).bold().cyan()
+            );
+        } else {
+            println!("{}", style(&sync.thunk_name.0).bold().cyan());
+        }
+
+        for path in &sync.migrated_from {
+            println!("  {} moved from {}", style(">").magenta(), path.display());
+        }
+
+        for path in &sync.created {
+            println!("  {} {}", style("+").green(), path.display());
+        }
+
+        for path in &sync.updated {
+            println!("  {} {}", style("~").yellow(), path.display());
+        }
+
+        for path in &sync.deleted {
+            println!("  {} {}", style("-").red(), path.display());
+        }
+    }
+
+    let total = |count: fn(&PackageSync) -> usize| syncs.iter().map(count).sum::<usize>();
+
+    println!(
+        "\nSynced {} packages ({} removed): {} created, {} updated, {} deleted, {} unchanged",
+        syncs.len(),
+        syncs.iter().filter(|sync| sync.removed).count(),
+        total(|sync| sync.created.len()),
+        total(|sync| sync.updated.len()),
+        total(|sync| sync.deleted.len()),
+        total(|sync| sync.unchanged),
+    );
+}
@@ -0,0 +1,129 @@
+use clap::Parser;
+use console::style;
+
+use crate::sources::{LicenseViolation, PackageRegistry};
+
+use super::{find_package, print_json, OutputFormat};
+
+/// Shows the version, size, licenses and dependencies of a single package.
+#[derive(Debug, Parser)]
+pub struct InfoCommand {
+    /// Thunk name (e.g. `React-9c8468d8-8a7220fd`) or true name (e.g. `React`) of the package.
+    pub package: String,
+}
+
+impl InfoCommand {
+    pub fn run(
+        self,
+        package_registry: &PackageRegistry,
+        format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        let package = find_package(package_registry, &self.package)?;
+
+        if format == OutputFormat::Json {
+            return print_json(package);
+        }
+
+        let licenses = package
+            .licenses
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        println!(
+            "{} ({})",
+            style(&package.true_name).bold().cyan(),
+            package.thunk_name.0
+        );
+        println!("Version: {}", package.version);
+
+        if let Some(parts) = &package.thunk_parts {
+            println!(
+                "Thunk: source hash {}, {}",
+                parts.source_hash, parts.revision
+            );
+        }
+
+        let other_versions = package_registry
+            .package_versions(&package.true_name)
+            .into_iter()
+            .filter(|other| other.thunk_name != package.thunk_name)
+            .map(|other| format!("{} ({})", other.version, other.thunk_name.0))
+            .collect::<Vec<String>>();
+
+        if !other_versions.is_empty() {
+            println!("Other versions: {}", other_versions.join(", "));
+        }
+        println!("Lines of code: {}", package.lines_of_code);
+        println!("Licenses: {licenses}");
+        println!("Source path: {:?}", package.package_path);
+
+        if !package.thunk_exported_types.is_empty() {
+            println!("Thunk types: {}", package.thunk_exported_types.join(", "));
+        }
+
+        let (waived, unlicensed): (Vec<_>, Vec<_>) = package_registry
+            .license_violations(package)
+            .into_iter()
+            .partition(LicenseViolation::is_waived);
+
+        if !unlicensed.is_empty() {
+            println!("\n{}", style("Unlicensed files:").bold().red());
+
+            for violation in &unlicensed {
+                println!("- {}", violation.describe());
+            }
+        }
+
+        if !waived.is_empty() {
+            println!("\n{}", style("Waived files:").bold().yellow());
+
+            for violation in &waived {
+                println!("- {}", violation.describe());
+            }
+        }
+
+        if !package.dependency_thunk_names.is_empty() {
+            println!("\n{}", style("Dependencies:").bold());
+
+            for (thunk_name, alias) in &package.dependency_thunk_names {
+                println!("- {alias} ({})", thunk_name.0);
+            }
+        }
+
+        if !package.unused_dependencies.is_empty() {
+            println!(
+                "\n{}",
+                style("Declared but never required:").bold().yellow()
+            );
+
+            for thunk_name in &package.unused_dependencies {
+                println!("- {}", thunk_name.0);
+            }
+        }
+
+        if !package.unresolved_requires.is_empty() {
+            println!(
+                "\n{}",
+                style("Requires that couldn't be followed (unused dependencies aren't checked):")
+                    .bold()
+                    .yellow()
+            );
+
+            for require in &package.unresolved_requires {
+                println!("- {require}");
+            }
+        }
+
+        if !package.undeclared_dependencies.is_empty() {
+            println!("\n{}", style("Required but not declared:").bold().red());
+
+            for name in &package.undeclared_dependencies {
+                println!("- {name}");
+            }
+        }
+
+        Ok(())
+    }
+}
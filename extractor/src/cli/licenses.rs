@@ -0,0 +1,172 @@
+use clap::Parser;
+
+use crate::constants::LICENSE_TEXTS;
+use crate::domain::PackageName;
+use crate::sources::{build_attribution_report, AttributionReport, PackageRegistry};
+
+use super::{print_json, OutputFormat};
+
+/// Prints a third-party attribution document for root packages and all of their dependencies:
+/// every package, its licenses and copyright holders, and the full text of each license. Text
+/// output is Markdown.
+#[derive(Debug, Parser)]
+pub struct LicensesCommand {
+    /// List of packages names to report on. Defaults to every package exposed from `Packages/`.
+    #[arg(short, long)]
+    pub root_packages: Vec<String>,
+}
+
+impl LicensesCommand {
+    pub fn run(
+        self,
+        package_registry: &PackageRegistry,
+        format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        let root_packages = if self.root_packages.is_empty() {
+            package_registry
+                .root_packages()
+                .map(|package| package.thunk_name.clone())
+                .collect()
+        } else {
+            self.root_packages
+                .iter()
+                .map(|i| PackageName(i.to_owned()))
+                .collect::<Vec<PackageName>>()
+        };
+
+        let report = build_attribution_report(package_registry, &root_packages)?;
+
+        match format {
+            OutputFormat::Text => print!("{}", render_markdown(&report)),
+            OutputFormat::Json => print_json(&report)?,
+        }
+
+        Ok(())
+    }
+}
+
+fn render_markdown(report: &AttributionReport) -> String {
+    let mut markdown = String::from("# Third-party licenses\n\n");
+
+    let root_packages = report
+        .root_packages
+        .iter()
+        .map(|thunk_name| format!("`{}`", thunk_name.0))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    markdown.push_str(&format!(
+        "Open source packages extracted from Roblox's CorePackages, included through {root_packages}.\n\n"
+    ));
+
+    markdown.push_str("## Licenses\n\n| License | Packages | Files |\n| --- | --- | --- |\n");
+    for summary in &report.licenses {
+        markdown.push_str(&format!(
+            "| {} | {} | {} |\n",
+            summary.spdx_id,
+            summary.packages.len(),
+            summary.files
+        ));
+    }
+
+    markdown.push_str("\n## Packages\n");
+    for package in &report.packages {
+        markdown.push_str(&format!("\n### {} {}\n\n", package.name, package.version));
+        markdown.push_str(&format!("- License: {}\n", package.license));
+        markdown.push_str(&format!("- Extracted from: `{}`\n", package.thunk_name.0));
+
+        if !package.copyright_holders.is_empty() {
+            markdown.push_str(&format!(
+                "- Copyright: {}\n",
+                package.copyright_holders.join("; ")
+            ));
+        }
+
+        let files = package
+            .files_per_license
+            .iter()
+            .map(|(spdx_id, count)| format!("{spdx_id} ({count})"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        markdown.push_str(&format!("- Files: {files}\n"));
+    }
+
+    markdown.push_str("\n## License texts\n");
+    for summary in &report.licenses {
+        let Some(text) = LICENSE_TEXTS.get(summary.spdx_id.as_str()) else {
+            continue;
+        };
+
+        markdown.push_str(&format!(
+            "\n### {}\n\n```text\n{text}```\n",
+            summary.spdx_id
+        ));
+    }
+
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use semver::Version;
+
+    use super::*;
+    use crate::domain::License;
+    use crate::sources::{LicenseSummary, PackageAttribution};
+
+    #[test]
+    fn renders_packages_and_license_texts() {
+        let report = AttributionReport {
+            root_packages: vec![PackageName("Root".to_owned())],
+            licenses: vec![
+                LicenseSummary {
+                    license: License::MIT,
+                    spdx_id: "MIT".to_owned(),
+                    packages: vec![PackageName("Root".to_owned())],
+                    files: 2,
+                },
+                LicenseSummary {
+                    license: License::Other("LicenseRef-Vendor".to_owned()),
+                    spdx_id: "LicenseRef-Vendor".to_owned(),
+                    packages: vec![PackageName("Root".to_owned())],
+                    files: 1,
+                },
+            ],
+            packages: vec![PackageAttribution {
+                thunk_name: PackageName("Root".to_owned()),
+                name: "core-packages/root".to_owned(),
+                version: Version::new(1, 2, 3),
+                license: "MIT AND LicenseRef-Vendor".to_owned(),
+                copyright_holders: vec!["Roblox Corporation".to_owned(), "Jane Doe".to_owned()],
+                files_per_license: BTreeMap::from([
+                    ("LicenseRef-Vendor".to_owned(), 1),
+                    ("MIT".to_owned(), 2),
+                ]),
+            }],
+        };
+
+        let markdown = render_markdown(&report);
+
+        assert!(markdown.starts_with(
+            "# Third-party licenses\n\n\
+             Open source packages extracted from Roblox's CorePackages, included through `Root`.\n\n\
+             ## Licenses\n\n\
+             | License | Packages | Files |\n\
+             | --- | --- | --- |\n\
+             | MIT | 1 | 2 |\n\
+             | LicenseRef-Vendor | 1 | 1 |\n\n\
+             ## Packages\n\n\
+             ### core-packages/root 1.2.3\n\n\
+             - License: MIT AND LicenseRef-Vendor\n\
+             - Extracted from: `Root`\n\
+             - Copyright: Roblox Corporation; Jane Doe\n\
+             - Files: LicenseRef-Vendor (1), MIT (2)\n\n\
+             ## License texts\n\n\
+             ### MIT\n\n```text\n"
+        ));
+        assert!(markdown.ends_with(&format!("{}```\n", LICENSE_TEXTS["MIT"])));
+        assert!(!markdown.contains("### LicenseRef-Vendor"));
+    }
+}
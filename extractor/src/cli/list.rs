@@ -0,0 +1,26 @@
+use clap::Parser;
+
+use crate::sources::PackageRegistry;
+
+use super::{print_json, OutputFormat};
+
+/// Lists every package in the index along with its license status.
+#[derive(Debug, Parser)]
+pub struct ListCommand {}
+
+impl ListCommand {
+    pub fn run(
+        self,
+        package_registry: &PackageRegistry,
+        format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        match format {
+            OutputFormat::Text => package_registry.print_packages(),
+            OutputFormat::Json => {
+                print_json(&package_registry.packages().collect::<Vec<_>>())?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+//! Defines the extractor's command line interface.
+
+mod check;
+mod diff;
+mod extract;
+mod info;
+mod licenses;
+mod list;
+mod similarity;
+mod tree;
+mod verify;
+mod why;
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::{Args, Parser, ValueEnum};
+use serde::Serialize;
+
+use crate::config::{ExtractorConfig, DEFAULT_CONFIG_FILE_NAME};
+use crate::domain::PackageMeta;
+use crate::sources::{
+    ClientTrackerPackageSource, CorePackageSource, DirectoryPackageSource, LocalPackageSource,
+    ModelPackageSource, PackageRegistry,
+};
+
+pub use self::check::CheckCommand;
+pub use self::diff::DiffCommand;
+pub use self::extract::ExtractCommand;
+pub use self::info::InfoCommand;
+pub use self::licenses::LicensesCommand;
+pub use self::list::ListCommand;
+pub use self::similarity::SimilarityCommand;
+pub use self::tree::TreeCommand;
+pub use self::verify::VerifyCommand;
+pub use self::why::WhyCommand;
+
+/// Extracts Roblox CorePackages into the specified directory, structured specifically for Wally.
+#[derive(Debug, Parser)]
+pub struct Options {
+    #[command(flatten)]
+    pub source: SourceOptions,
+
+    /// Path to an `extractor.toml` config file. Defaults to `extractor.toml` in the working
+    /// directory if one exists, otherwise the built-in defaults are used.
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Format of results written to stdout. Progress and warnings always go to stderr.
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    #[command(subcommand)]
+    pub subcommand: Subcommand,
+}
+
+impl Options {
+    pub fn run(self) -> anyhow::Result<()> {
+        let config = self.load_config()?;
+        let format = self.format;
+
+        // Diffing loads its own pair of sources
+        let subcommand = match self.subcommand {
+            Subcommand::Diff(subcommand) => return subcommand.run(&self.source, config, format),
+            subcommand => subcommand,
+        };
+
+        // The source has to outlive the registry, some sources unpack into temporary directories
+        // that package paths point into.
+        let source = self.source.open()?;
+
+        let package_registry = source
+            .load_registry(config)
+            .context("Failed to load CorePackages")?;
+
+        package_registry
+            .config()
+            .check_package_references(package_registry.index_names())
+            .context("Invalid config")?;
+
+        match subcommand {
+            Subcommand::List(subcommand) => subcommand.run(&package_registry, format),
+            Subcommand::Info(subcommand) => subcommand.run(&package_registry, format),
+            Subcommand::Tree(subcommand) => subcommand.run(&package_registry, format),
+            Subcommand::Why(subcommand) => subcommand.run(&package_registry, format),
+            Subcommand::Check(subcommand) => subcommand.run(&package_registry, format),
+            Subcommand::Licenses(subcommand) => subcommand.run(&package_registry, format),
+            Subcommand::Extract(subcommand) => subcommand.run(&package_registry, format),
+            Subcommand::Verify(subcommand) => subcommand.run(&package_registry, format),
+            Subcommand::Similarity(subcommand) => subcommand.run(&package_registry, format),
+            Subcommand::Diff(_) => unreachable!("diff is handled before loading a registry"),
+        }
+    }
+
+    fn load_config(&self) -> anyhow::Result<ExtractorConfig> {
+        let path = match &self.config {
+            Some(path) => resolve_path(path),
+            None => {
+                let path = resolve_path(Path::new(DEFAULT_CONFIG_FILE_NAME));
+                if !path.exists() {
+                    return Ok(ExtractorConfig::default());
+                }
+
+                path
+            }
+        };
+
+        eprintln!("Using config file at: {path:?}");
+
+        ExtractorConfig::load(&path).context("Failed to load config")
+    }
+}
+
+/// Where to read CorePackages from. Defaults to the detected Roblox Studio installation.
+#[derive(Debug, Args)]
+pub struct SourceOptions {
+    /// Read CorePackages from this directory (laid out like `Packages/`, with an `_Index/`)
+    /// instead of a detected Roblox Studio installation.
+    #[arg(short, long, global = true, conflicts_with_all = ["model", "client_tracker"])]
+    pub packages_path: Option<PathBuf>,
+
+    /// Read CorePackages from the `Packages` folder inside a binary or XML model or place file
+    /// (`.rbxm`/`.rbxl`/`.rbxmx`/`.rbxlx`).
+    #[arg(short, long, global = true, conflicts_with = "client_tracker")]
+    pub model: Option<PathBuf>,
+
+    /// Read CorePackages from a local mirror of the Client Tracker (a directory of versioned
+    /// Roblox releases, each containing `LuaPackages/Packages`).
+    #[arg(long, global = true)]
+    pub client_tracker: Option<PathBuf>,
+
+    /// Client Tracker version to extract from. Defaults to the newest version.
+    #[arg(long, global = true, requires = "client_tracker")]
+    pub studio_version: Option<String>,
+}
+
+impl SourceOptions {
+    pub fn open(&self) -> anyhow::Result<Box<dyn CorePackageSource>> {
+        let source: Box<dyn CorePackageSource> =
+            match (&self.packages_path, &self.model, &self.client_tracker) {
+                (Some(packages_path), _, _) => {
+                    Box::new(DirectoryPackageSource::new(resolve_path(packages_path)))
+                }
+                (_, Some(model), _) => Box::new(
+                    ModelPackageSource::open(&resolve_path(model))
+                        .context("Failed to open model file")?,
+                ),
+                (_, _, Some(client_tracker)) => Box::new(ClientTrackerPackageSource::new(
+                    resolve_path(client_tracker),
+                    self.studio_version.clone(),
+                )),
+                (None, None, None) => Box::new(LocalPackageSource),
+            };
+
+        Ok(source)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable, coloured text.
+    Text,
+    /// Structured JSON, for consuming from other tools.
+    Json,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Subcommand {
+    List(ListCommand),
+    Info(InfoCommand),
+    Tree(TreeCommand),
+    Why(WhyCommand),
+    Check(CheckCommand),
+    Licenses(LicensesCommand),
+    Extract(ExtractCommand),
+    Verify(VerifyCommand),
+    Similarity(SimilarityCommand),
+    Diff(DiffCommand),
+}
+
+/// Looks up a package by thunk name or true name, erroring if it doesn't exist.
+fn find_package<'a>(
+    package_registry: &'a PackageRegistry,
+    name: &str,
+) -> anyhow::Result<&'a PackageMeta> {
+    package_registry
+        .find_package(name)
+        .context(format!("Package {name:?} does not exist in registry"))
+}
+
+fn print_json<T: Serialize>(value: &T) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(value).context("Failed to serialize output")?;
+    println!("{json}");
+
+    Ok(())
+}
+
+// https://github.com/rojo-rbx/rojo/blob/b88d34c639b7d7bdd4171b7846a64c2b13f0c2d5/src/cli/mod.rs#L124
+fn resolve_path(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_owned()
+    } else {
+        env::current_dir().unwrap().join(path)
+    }
+}
@@ -0,0 +1,85 @@
+use clap::Parser;
+use console::style;
+
+use crate::constants::MAX_REPLACEMENT_SIMILARITY;
+use crate::sources::{compare_replacements, PackageRegistry, ReplacementSimilarity};
+
+use super::{print_json, OutputFormat};
+
+/// Compares each source replacement against the upstream file it replaces, token by token, and
+/// warns when a rewrite is too close to the original to count as independent.
+#[derive(Debug, Parser)]
+pub struct SimilarityCommand {
+    /// Share of tokens, from 0 to 1, a replacement can have in common with the original before
+    /// it's flagged.
+    #[arg(long, default_value_t = MAX_REPLACEMENT_SIMILARITY, value_parser = parse_threshold)]
+    pub threshold: f32,
+}
+
+fn parse_threshold(value: &str) -> Result<f32, String> {
+    let threshold = value
+        .parse::<f32>()
+        .map_err(|_| format!("{value:?} is not a number"))?;
+
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(format!("{threshold} is not between 0 and 1"));
+    }
+
+    Ok(threshold)
+}
+
+impl SimilarityCommand {
+    pub fn run(
+        self,
+        package_registry: &PackageRegistry,
+        format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        let comparisons = compare_replacements(package_registry, self.threshold)?;
+
+        match format {
+            OutputFormat::Text => print_comparisons(&comparisons),
+            OutputFormat::Json => print_json(&comparisons)?,
+        }
+
+        for comparison in comparisons.iter().filter(|c| c.too_similar) {
+            eprintln!(
+                "WARN: Replacement {:?} shares {:.0}% of its tokens with the original (threshold {:.0}%), rewrite it further",
+                comparison.replacement,
+                comparison.similarity * 100.0,
+                self.threshold * 100.0
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn print_comparisons(comparisons: &[ReplacementSimilarity]) {
+    if comparisons.is_empty() {
+        println!("\nNo files are replaced");
+        return;
+    }
+
+    println!();
+
+    for comparison in comparisons {
+        let similarity = format!("{:.0}%", comparison.similarity * 100.0);
+        let similarity = if comparison.too_similar {
+            style(similarity).red().bold()
+        } else {
+            style(similarity).green()
+        };
+
+        println!(
+            "{} {}",
+            style(&comparison.package.0).bold().cyan(),
+            comparison.file.display()
+        );
+        println!(
+            "  {similarity} similar, longest shared run of {} tokens ({} original, {} replacement tokens)",
+            comparison.longest_shared_run,
+            comparison.original_tokens,
+            comparison.replacement_tokens
+        );
+    }
+}
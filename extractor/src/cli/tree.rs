@@ -0,0 +1,130 @@
+use std::collections::BTreeSet;
+
+use clap::Parser;
+use serde::Serialize;
+
+use crate::domain::PackageName;
+use crate::sources::PackageRegistry;
+
+use super::{find_package, print_json, OutputFormat};
+
+/// Prints the resolved dependency tree of a package.
+#[derive(Debug, Parser)]
+pub struct TreeCommand {
+    /// Thunk name (e.g. `React-9c8468d8-8a7220fd`) or true name (e.g. `React`) of the package.
+    pub package: String,
+}
+
+impl TreeCommand {
+    pub fn run(
+        self,
+        package_registry: &PackageRegistry,
+        format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        let package = find_package(package_registry, &self.package)?;
+
+        let mut expanded = BTreeSet::new();
+        let tree = build_tree(package_registry, &package.thunk_name, &mut expanded);
+
+        match format {
+            OutputFormat::Text => {
+                println!("{}", tree.describe());
+                print_dependencies(&tree, "");
+            }
+            OutputFormat::Json => print_json(&tree)?,
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TreeNode<'a> {
+    thunk_name: &'a PackageName,
+    true_name: Option<&'a str>,
+    version: Option<String>,
+    /// External Wally package this dependency is replaced with.
+    alias: Option<&'a str>,
+    /// Whether the package isn't in the registry at all.
+    missing: bool,
+    /// Whether the package was already expanded elsewhere in the tree, in which case its
+    /// dependencies are left out.
+    duplicate: bool,
+    dependencies: Vec<TreeNode<'a>>,
+}
+
+impl TreeNode<'_> {
+    fn describe(&self) -> String {
+        let thunk_name = self.thunk_name.as_str();
+
+        if let Some(alias) = self.alias {
+            return format!("{thunk_name} ({alias})");
+        }
+
+        match (self.true_name, &self.version) {
+            (Some(true_name), Some(version)) => format!("{thunk_name} ({true_name} {version})"),
+            _ => format!("{thunk_name} (missing)"),
+        }
+    }
+}
+
+fn build_tree<'a>(
+    package_registry: &'a PackageRegistry,
+    thunk_name: &'a PackageName,
+    expanded: &mut BTreeSet<&'a PackageName>,
+) -> TreeNode<'a> {
+    let alias = package_registry
+        .config()
+        .dependency_aliases
+        .get(thunk_name.as_str())
+        .map(String::as_str);
+    let package = package_registry.get_package(thunk_name);
+
+    let mut node = TreeNode {
+        thunk_name,
+        true_name: package.map(|package| package.true_name.as_str()),
+        version: package.map(|package| package.version.to_string()),
+        alias,
+        missing: package.is_none(),
+        duplicate: false,
+        dependencies: Vec::new(),
+    };
+
+    // Like `cargo tree`, only expand each package the first time it's seen
+    if !expanded.insert(thunk_name) {
+        node.duplicate = true;
+        return node;
+    }
+
+    if let Some(package) = package {
+        node.dependencies = package
+            .dependencies
+            .iter()
+            .map(|dependency| build_tree(package_registry, dependency, expanded))
+            .collect();
+    }
+
+    node
+}
+
+fn print_dependencies(node: &TreeNode, prefix: &str) {
+    let count = node.dependencies.len();
+    for (index, dependency) in node.dependencies.iter().enumerate() {
+        let last = index + 1 == count;
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+
+        let description = dependency.describe();
+
+        if dependency.duplicate {
+            println!("{prefix}{branch}{description} (*)");
+            continue;
+        }
+
+        println!("{prefix}{branch}{description}");
+        print_dependencies(dependency, &format!("{prefix}{indent}"));
+    }
+}
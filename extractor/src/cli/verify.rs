@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use clap::Parser;
+use console::style;
+
+use crate::sources::{verify_extraction, DriftKind, FileDrift, PackageRegistry, Verification};
+
+use super::{print_json, resolve_path, OutputFormat};
+
+/// Checks that an output directory still matches what the extractor wrote, reporting files that
+/// were edited by hand or would be written differently by a fresh extraction.
+#[derive(Debug, Parser)]
+pub struct VerifyCommand {
+    /// Directory packages were extracted to, containing an `extractor.lock`.
+    pub output_path: PathBuf,
+}
+
+impl VerifyCommand {
+    pub fn run(
+        self,
+        package_registry: &PackageRegistry,
+        format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        let path = resolve_path(&self.output_path);
+        let verification = verify_extraction(package_registry, &path)?;
+
+        // Print the full report first so the exit code can still signal failure
+        match format {
+            OutputFormat::Text => print_verification(&verification),
+            OutputFormat::Json => print_json(&verification)?,
+        }
+
+        if !verification.drift.is_empty() {
+            bail!(
+                "{} files in {path:?} were changed by hand, packages must only be changed through the extractor",
+                verification.drift.len()
+            );
+        }
+
+        if !verification.outdated.is_empty() {
+            bail!(
+                "{} files in {path:?} are out of date, re-extract with --update",
+                verification.outdated.len()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn print_verification(verification: &Verification) {
+    if verification.drift.is_empty() && verification.outdated.is_empty() {
+        println!("\nOutput matches the extractor");
+        return;
+    }
+
+    if !verification.drift.is_empty() {
+        println!("\n{}", style("Changed by hand:").bold());
+        print_drift(&verification.drift);
+    }
+
+    if !verification.outdated.is_empty() {
+        println!("\n{}", style("Changed upstream:").bold());
+        print_drift(&verification.outdated);
+    }
+}
+
+fn print_drift(drift: &[FileDrift]) {
+    for file_drift in drift {
+        let (marker, note) = match file_drift.kind {
+            DriftKind::Edited => (style("~").yellow(), "edited by hand"),
+            DriftKind::Missing => (style("-").red(), "deleted by hand"),
+            DriftKind::Untracked => (style("+").green(), "not written by the extractor"),
+            DriftKind::Outdated => (style("!").cyan(), "out of date, re-extract with --update"),
+        };
+
+        println!("{marker} {} ({note})", file_drift.path.display());
+    }
+}
@@ -0,0 +1,56 @@
+use clap::Parser;
+use console::style;
+
+use crate::sources::PackageRegistry;
+
+use super::{find_package, print_json, OutputFormat};
+
+/// Shows which root packages pull in a package, and through which dependencies (the shortest
+/// chain from each root).
+#[derive(Debug, Parser)]
+pub struct WhyCommand {
+    /// Thunk name (e.g. `LuauPolyfill-2fca3173-1.1.0`) or true name (e.g. `LuauPolyfill`) of the
+    /// package.
+    pub package: String,
+}
+
+impl WhyCommand {
+    pub fn run(
+        self,
+        package_registry: &PackageRegistry,
+        format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        let package = find_package(package_registry, &self.package)?;
+        let chains = package_registry.dependency_chains(&package.thunk_name);
+
+        if format == OutputFormat::Json {
+            return print_json(&chains);
+        }
+
+        if chains.is_empty() {
+            println!(
+                "{} is not required by any root package",
+                style(&package.thunk_name.0).bold().cyan()
+            );
+
+            return Ok(());
+        }
+
+        println!(
+            "{} is required by:\n",
+            style(&package.thunk_name.0).bold().cyan()
+        );
+
+        for chain in chains {
+            let chain = chain
+                .iter()
+                .map(|thunk_name| thunk_name.as_str())
+                .collect::<Vec<&str>>()
+                .join(" -> ");
+
+            println!("- {chain}");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,519 @@
+//! Runtime configuration for the extractor, loaded from an `extractor.toml`.
+//!
+//! Every table is optional. Tables missing from the file fall back to the defaults in
+//! [`crate::constants`], tables that are present replace them entirely.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use semver::Version;
+use serde::Deserialize;
+
+use crate::constants::{
+    ALLOWED_LICENSES, ALLOWED_MODULES, BANNED_PACKAGE_NAMES, DENIED_LICENSES, DEPENDENCY_ALIASES,
+    PACKAGE_VERSION_OVERRIDES, SOURCE_REPLACEMENTS,
+};
+use crate::domain::License;
+
+/// Name of the config file picked up from the working directory when `--config` isn't given.
+pub const DEFAULT_CONFIG_FILE_NAME: &str = "extractor.toml";
+
+#[derive(Debug, Clone)]
+pub struct ExtractorConfig {
+    /// Packages that are unlicensed and can be replaced with something else, or old versions of
+    /// packages that shouldn't be extracted.
+    pub banned_packages: Vec<String>,
+    /// Packages that are swapped out for an external Wally package, keyed by thunk name.
+    pub dependency_aliases: BTreeMap<String, String>,
+    /// Versions to publish packages under instead of the one in their `lock.toml`.
+    pub version_overrides: BTreeMap<String, Version>,
+    /// Dependencies pointed at another version of the same package, keyed by the thunk name of the
+    /// consumer and then of the dependency, e.g. `LuauPolyfill-2fca3173-0.4.2` to
+    /// `LuauPolyfill-2fca3173-1.1.0`.
+    pub dependency_redirects: BTreeMap<String, BTreeMap<String, String>>,
+    /// Modules too small to be rewritten, which are assumed to be MIT.
+    pub allowed_modules: Vec<String>,
+    /// Rewritten sources for unlicensed modules, keyed by a path suffix of the original module.
+    pub source_replacements: BTreeMap<String, String>,
+    pub license_policy: LicensePolicy,
+    /// Packages named by the config file, see [`ExtractorConfig::check_package_references`].
+    pub package_references: Vec<PackageReference>,
+}
+
+/// A package named in the config file, along with the key naming it, e.g.
+/// `version-overrides."RoactCompat-9c8468d8-8a7220fd"`.
+#[derive(Debug, Clone)]
+pub struct PackageReference {
+    pub key: String,
+    pub package: String,
+}
+
+/// Which licenses code can be extracted under, and exceptions for specific packages or files.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LicensePolicy {
+    /// SPDX identifiers of licenses that can be extracted.
+    #[serde(default = "default_allowed_licenses")]
+    pub allowed: Vec<String>,
+    /// SPDX identifiers of licenses that are never extracted, even if also allowed.
+    #[serde(default = "default_denied_licenses")]
+    pub denied: Vec<String>,
+    #[serde(default)]
+    pub waivers: Vec<LicenseWaiver>,
+}
+
+/// Lets a package, or a single file in it, be extracted despite failing the license policy.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LicenseWaiver {
+    /// Thunk name or true name of the package.
+    pub package: String,
+    /// Path suffix of a single file in the package, like
+    /// `Scheduler/forks/SchedulerHostConfig.lua`. The whole package is covered if unset.
+    pub file: Option<String>,
+    /// SPDX identifiers the waiver covers. Every license is covered if empty.
+    #[serde(default)]
+    pub licenses: Vec<String>,
+    pub reason: String,
+    /// Last day the waiver applies, as `YYYY-MM-DD`.
+    pub expires: String,
+}
+
+impl Default for LicensePolicy {
+    fn default() -> Self {
+        Self {
+            allowed: default_allowed_licenses(),
+            denied: default_denied_licenses(),
+            waivers: Vec::new(),
+        }
+    }
+}
+
+impl LicensePolicy {
+    pub fn is_denied(&self, license: &License) -> bool {
+        contains_license(&self.denied, license)
+    }
+
+    pub fn is_allowed(&self, license: &License) -> bool {
+        !self.is_denied(license) && contains_license(&self.allowed, license)
+    }
+}
+
+impl LicenseWaiver {
+    /// Whether the waiver has run out, given today's date as `YYYY-MM-DD`.
+    pub fn is_expired(&self, today: &str) -> bool {
+        // Dates in this format sort the same as text
+        today > self.expires.as_str()
+    }
+}
+
+fn contains_license(ids: &[String], license: &License) -> bool {
+    ids.iter()
+        .any(|id| id.eq_ignore_ascii_case(license.spdx_id()))
+}
+
+fn default_allowed_licenses() -> Vec<String> {
+    ALLOWED_LICENSES.iter().map(|i| i.to_string()).collect()
+}
+
+fn default_denied_licenses() -> Vec<String> {
+    DENIED_LICENSES.iter().map(|i| i.to_string()).collect()
+}
+
+impl Default for ExtractorConfig {
+    fn default() -> Self {
+        Self {
+            banned_packages: BANNED_PACKAGE_NAMES.iter().map(|i| i.to_string()).collect(),
+            dependency_aliases: DEPENDENCY_ALIASES
+                .entries()
+                .map(|(name, alias)| (name.to_string(), alias.to_string()))
+                .collect(),
+            version_overrides: PACKAGE_VERSION_OVERRIDES
+                .entries()
+                .map(|(name, version)| {
+                    (
+                        name.to_string(),
+                        Version::from_str(version).expect("Valid default version override"),
+                    )
+                })
+                .collect(),
+            dependency_redirects: BTreeMap::new(),
+            allowed_modules: ALLOWED_MODULES.iter().map(|i| i.to_string()).collect(),
+            source_replacements: SOURCE_REPLACEMENTS
+                .entries()
+                .map(|(path, content)| (path.to_string(), content.to_string()))
+                .collect(),
+            license_policy: LicensePolicy::default(),
+            package_references: Vec::new(),
+        }
+    }
+}
+
+impl ExtractorConfig {
+    /// Loads a config file, filling in anything it doesn't specify with the defaults.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path).context(format!("Failed to read {path:?}"))?;
+        let file = toml::from_str::<ConfigFile>(&content).context(format!("Invalid {path:?}"))?;
+
+        // Source replacements are resolved relative to the config file
+        let base_path = path.parent().unwrap_or_else(|| Path::new(""));
+
+        file.validate(base_path)
+            .context(format!("Invalid {path:?}"))
+    }
+
+    /// Checks that every package the config file names is in the index, since a misspelled one
+    /// would silently do nothing. Built-in defaults aren't checked, as they cover packages from
+    /// every Roblox release.
+    pub fn check_package_references<'a>(
+        &self,
+        index_names: impl IntoIterator<Item = &'a str>,
+    ) -> anyhow::Result<()> {
+        let index_names = index_names.into_iter().collect::<BTreeSet<&str>>();
+
+        let errors = self
+            .package_references
+            .iter()
+            .filter(|reference| !index_names.contains(reference.package.as_str()))
+            .map(|reference| {
+                format!(
+                    "{}: no package named {:?} in the index",
+                    reference.key, reference.package
+                )
+            })
+            .collect::<Vec<String>>();
+
+        if !errors.is_empty() {
+            bail!(errors.join("\n"));
+        }
+
+        Ok(())
+    }
+
+    pub fn is_banned(&self, package_name: &str) -> bool {
+        self.banned_packages.iter().any(|i| i == package_name)
+    }
+
+    pub fn is_aliased(&self, package_name: &str) -> bool {
+        self.dependency_aliases.contains_key(package_name)
+    }
+
+    fn add_package_reference(&mut self, key: String, package: &str) {
+        if !package.trim().is_empty() {
+            self.package_references.push(PackageReference {
+                key,
+                package: package.to_owned(),
+            });
+        }
+    }
+}
+
+/// The raw contents of an `extractor.toml`, before validation.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ConfigFile {
+    banned_packages: Option<Vec<String>>,
+    dependency_aliases: Option<BTreeMap<String, String>>,
+    version_overrides: Option<BTreeMap<String, String>>,
+    dependency_redirects: Option<BTreeMap<String, BTreeMap<String, String>>>,
+    allowed_modules: Option<Vec<String>>,
+    /// Maps a path suffix of the original module to the file containing its rewrite.
+    source_replacements: Option<BTreeMap<String, String>>,
+    license_policy: Option<LicensePolicy>,
+}
+
+impl ConfigFile {
+    /// Validates every entry, reporting all offending keys at once rather than just the first.
+    fn validate(self, base_path: &Path) -> anyhow::Result<ExtractorConfig> {
+        let mut config = ExtractorConfig::default();
+        let mut errors = Vec::new();
+
+        if let Some(banned_packages) = self.banned_packages {
+            check_not_empty("banned-packages", &banned_packages, &mut errors);
+
+            for (index, package) in banned_packages.iter().enumerate() {
+                config.add_package_reference(format!("banned-packages[{index}]"), package);
+            }
+
+            config.banned_packages = banned_packages;
+        }
+
+        if let Some(allowed_modules) = self.allowed_modules {
+            check_not_empty("allowed-modules", &allowed_modules, &mut errors);
+            config.allowed_modules = allowed_modules;
+        }
+
+        if let Some(dependency_aliases) = self.dependency_aliases {
+            for (package_name, alias) in &dependency_aliases {
+                if !is_wally_package_reference(alias) {
+                    errors.push(format!(
+                        "dependency-aliases.{package_name:?}: expected \"scope/name@version\", found {alias:?}"
+                    ));
+                }
+            }
+
+            config.dependency_aliases = dependency_aliases;
+        }
+
+        if let Some(version_overrides) = self.version_overrides {
+            config.version_overrides.clear();
+
+            for (package_name, version) in version_overrides {
+                config.add_package_reference(
+                    format!("version-overrides.{package_name:?}"),
+                    &package_name,
+                );
+
+                match Version::from_str(&version) {
+                    Ok(parsed) => {
+                        config.version_overrides.insert(package_name, parsed);
+                    }
+                    Err(err) => errors.push(format!(
+                        "version-overrides.{package_name:?}: {version:?} is not a valid version ({err})"
+                    )),
+                }
+            }
+        }
+
+        if let Some(dependency_redirects) = self.dependency_redirects {
+            for (consumer, redirects) in &dependency_redirects {
+                config
+                    .add_package_reference(format!("dependency-redirects.{consumer:?}"), consumer);
+
+                for (dependency, replacement) in redirects {
+                    let key = format!("dependency-redirects.{consumer:?}.{dependency:?}");
+
+                    if replacement.trim().is_empty() {
+                        errors.push(format!("{key}: must not be empty"));
+                    }
+
+                    config.add_package_reference(key.clone(), dependency);
+                    config.add_package_reference(key, replacement);
+                }
+            }
+
+            config.dependency_redirects = dependency_redirects;
+        }
+
+        if let Some(source_replacements) = self.source_replacements {
+            config.source_replacements.clear();
+
+            for (module_path, replacement_path) in source_replacements {
+                let full_path = base_path.join(&replacement_path);
+
+                match fs::read_to_string(&full_path) {
+                    Ok(content) => {
+                        config.source_replacements.insert(module_path, content);
+                    }
+                    Err(err) => errors.push(format!(
+                        "source-replacements.{module_path:?}: failed to read {full_path:?} ({err})"
+                    )),
+                }
+            }
+        }
+
+        if let Some(license_policy) = self.license_policy {
+            check_not_empty(
+                "license-policy.allowed",
+                &license_policy.allowed,
+                &mut errors,
+            );
+
+            for (index, waiver) in license_policy.waivers.iter().enumerate() {
+                let key = format!("license-policy.waivers[{index}]");
+
+                if waiver.reason.trim().is_empty() {
+                    errors.push(format!("{key}.reason: must not be empty"));
+                }
+
+                if !is_date(&waiver.expires) {
+                    errors.push(format!(
+                        "{key}.expires: expected a \"YYYY-MM-DD\" date, found {:?}",
+                        waiver.expires
+                    ));
+                }
+            }
+
+            config.license_policy = license_policy;
+        }
+
+        if !errors.is_empty() {
+            bail!(errors.join("\n"));
+        }
+
+        Ok(config)
+    }
+}
+
+fn check_not_empty(key: &str, values: &[String], errors: &mut Vec<String>) {
+    for (index, value) in values.iter().enumerate() {
+        if value.trim().is_empty() {
+            errors.push(format!("{key}[{index}]: must not be empty"));
+        }
+    }
+}
+
+fn is_date(date: &str) -> bool {
+    date.len() == 10 && humantime::parse_rfc3339(&format!("{date}T00:00:00Z")).is_ok()
+}
+
+fn is_wally_package_reference(reference: &str) -> bool {
+    let Some((name, version)) = reference.split_once('@') else {
+        return false;
+    };
+
+    let Some((scope, name)) = name.split_once('/') else {
+        return false;
+    };
+
+    !scope.is_empty() && !name.is_empty() && !version.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(contents: &str) -> anyhow::Result<ExtractorConfig> {
+        toml::from_str::<ConfigFile>(contents)?.validate(Path::new(""))
+    }
+
+    fn errors(contents: &str) -> Vec<String> {
+        validate(contents)
+            .unwrap_err()
+            .to_string()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn missing_tables_keep_the_defaults() {
+        let config = validate("banned-packages = [\"Cryo\"]").unwrap();
+
+        assert_eq!(config.banned_packages, vec!["Cryo"]);
+        assert_eq!(
+            config.allowed_modules,
+            ExtractorConfig::default().allowed_modules
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<ConfigFile>("banned-package = [\"Cryo\"]").is_err());
+    }
+
+    #[test]
+    fn every_error_points_at_its_key() {
+        let errors = errors(
+            r#"
+            banned-packages = ["Cryo", " "]
+            allowed-modules = [""]
+
+            [dependency-aliases]
+            Promise = "evaera/promise"
+
+            [version-overrides]
+            "RoactCompat-9c8468d8-8a7220fd" = "17"
+
+            [dependency-redirects."React-9c8468d8-8a7220fd"]
+            "LuauPolyfill-2fca3173-0.4.2" = ""
+
+            [source-replacements]
+            "RoactCompat/init.lua" = "missing/RoactCompat.lua"
+
+            [license-policy]
+            allowed = [""]
+
+            [[license-policy.waivers]]
+            package = "Scheduler"
+            reason = ""
+            expires = "30/06/2024"
+            "#,
+        );
+
+        let keys = errors
+            .iter()
+            .map(|error| error.split_once(": ").unwrap().0)
+            .collect::<Vec<&str>>();
+
+        assert_eq!(
+            keys,
+            vec![
+                "banned-packages[1]",
+                "allowed-modules[0]",
+                "dependency-aliases.\"Promise\"",
+                "version-overrides.\"RoactCompat-9c8468d8-8a7220fd\"",
+                "dependency-redirects.\"React-9c8468d8-8a7220fd\".\"LuauPolyfill-2fca3173-0.4.2\"",
+                "source-replacements.\"RoactCompat/init.lua\"",
+                "license-policy.allowed[0]",
+                "license-policy.waivers[0].reason",
+                "license-policy.waivers[0].expires",
+            ]
+        );
+    }
+
+    #[test]
+    fn source_replacements_are_read_relative_to_the_config() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("init.lua"), "return {}").unwrap();
+
+        let config = toml::from_str::<ConfigFile>(
+            "[source-replacements]\n\"RoactCompat/init.lua\" = \"init.lua\"",
+        )
+        .unwrap()
+        .validate(directory.path())
+        .unwrap();
+
+        assert_eq!(
+            config.source_replacements,
+            BTreeMap::from([("RoactCompat/init.lua".to_owned(), "return {}".to_owned())])
+        );
+    }
+
+    #[test]
+    fn packages_missing_from_the_index_point_at_their_key() {
+        let config = validate(
+            r#"
+            banned-packages = ["LuauPolyfill-2fca3173-0.4.2", "Reactt"]
+
+            [version-overrides]
+            "Shared-9c8468d8-8a7220fdx" = "1.0.0"
+
+            [dependency-redirects."React-9c8468d8-8a7220fd"]
+            "LuauPolyfill-2fca3173-1.1.0" = "LuauPolyfill-2fca3173-1.2.0"
+            "#,
+        )
+        .unwrap();
+
+        let index_names = [
+            "LuauPolyfill-2fca3173-0.4.2",
+            "LuauPolyfill-2fca3173-1.1.0",
+            "React-9c8468d8-8a7220fd",
+            "Shared-9c8468d8-8a7220fd",
+        ];
+
+        let error = config
+            .check_package_references(index_names)
+            .unwrap_err()
+            .to_string();
+
+        assert_eq!(
+            error.lines().collect::<Vec<&str>>(),
+            vec![
+                "banned-packages[1]: no package named \"Reactt\" in the index",
+                "version-overrides.\"Shared-9c8468d8-8a7220fdx\": no package named \"Shared-9c8468d8-8a7220fdx\" in the index",
+                "dependency-redirects.\"React-9c8468d8-8a7220fd\".\"LuauPolyfill-2fca3173-1.1.0\": no package named \"LuauPolyfill-2fca3173-1.2.0\" in the index",
+            ]
+        );
+    }
+
+    #[test]
+    fn defaults_are_not_checked_against_the_index() {
+        assert!(ExtractorConfig::default()
+            .check_package_references([])
+            .is_ok());
+    }
+}
@@ -1,5 +1,9 @@
+//! Built-in defaults for the tables in `extractor.toml`, see [`crate::config`].
+
 use phf::phf_map;
 
+use crate::domain::License;
+
 /// Some packages are unlicensed and can be replaced with something else. Block them
 /// entirely here.
 pub const BANNED_PACKAGE_NAMES: [&str; 15] = [
@@ -30,12 +34,69 @@ pub static PACKAGE_VERSION_OVERRIDES: phf::Map<&'static str, &'static str> = phf
     "RoactCompat-9c8468d8-8a7220fd" => "17.0.1-rc.16.1",
 };
 
-pub const MIT_LICENSE_PHRASES: [&str; 2] = [
-    "licensed under the MIT license",
-    "Copyright Node.js contributors. All rights reserved",
+/// SPDX identifiers of the licenses code can be extracted under.
+pub const ALLOWED_LICENSES: [&str; 6] = [
+    "MIT",
+    "Apache-2.0",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "ISC",
+    "MPL-2.0",
 ];
 
-pub const APACHE_LICENSE_PHRASES: [&str; 1] = ["licensed under the Apache License, Version 2.0"];
+/// SPDX identifiers of licenses that are never extracted, even if also allowed.
+pub const DENIED_LICENSES: [&str; 1] = ["LicenseRef-Roblox-Proprietary"];
+
+/// Wording from standard license headers, matched against a file's leading comments when it has no
+/// `SPDX-License-Identifier`. Case and line wrapping are ignored.
+pub const LICENSE_HEADER_TEMPLATES: [(&str, License); 11] = [
+    ("licensed under the MIT license", License::MIT),
+    (
+        "Permission is hereby granted, free of charge, to any person obtaining a copy",
+        License::MIT,
+    ),
+    (
+        "Copyright Node.js contributors. All rights reserved",
+        License::MIT,
+    ),
+    (
+        "licensed under the Apache License, Version 2.0",
+        License::Apache2,
+    ),
+    (
+        "Redistribution and use in source and binary forms, with or without modification, are permitted",
+        License::BSD2Clause,
+    ),
+    (
+        "nor the names of its contributors may be used to endorse or promote products",
+        License::BSD3Clause,
+    ),
+    (
+        "distribute this software for any purpose with or without fee is hereby granted",
+        License::ISC,
+    ),
+    (
+        "subject to the terms of the Mozilla Public License, v. 2.0",
+        License::MPL2,
+    ),
+    (
+        "Mozilla Public License Version 2.0",
+        License::MPL2,
+    ),
+    ("Roblox proprietary", License::RobloxProprietary),
+    ("Proprietary and confidential", License::RobloxProprietary),
+];
+
+/// Full text of every license code can be extracted under, keyed by SPDX identifier. The MIT,
+/// BSD and ISC texts leave out their copyright line, which is filled in from file headers.
+pub static LICENSE_TEXTS: phf::Map<&'static str, &'static str> = phf_map! {
+    "MIT" => include_str!("../resources/licenses/MIT.txt"),
+    "Apache-2.0" => include_str!("../resources/licenses/Apache-2.0.txt"),
+    "BSD-2-Clause" => include_str!("../resources/licenses/BSD-2-Clause.txt"),
+    "BSD-3-Clause" => include_str!("../resources/licenses/BSD-3-Clause.txt"),
+    "ISC" => include_str!("../resources/licenses/ISC.txt"),
+    "MPL-2.0" => include_str!("../resources/licenses/MPL-2.0.txt"),
+};
 
 // Some modules are so small that it's impossible to rewrite them enough to be considered unique.
 // Explicitly allow those modules here.
@@ -47,6 +108,10 @@ pub const ALLOWED_MODULES: [&str; 5] = [
     "InstanceOf/InstanceOf/init.lua",
 ];
 
+// Share of tokens a source replacement can have in common with the file it replaces before it's
+// flagged as too close to the original to count as a rewrite
+pub const MAX_REPLACEMENT_SIMILARITY: f32 = 0.5;
+
 // Any module that needs to be rewritten should be included here
 pub static SOURCE_REPLACEMENTS: phf::Map<&'static str, &'static str> = phf_map! {
     "Scheduler/getJestMatchers.roblox.lua" =>
@@ -1,63 +1,287 @@
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::PathBuf,
+    str::FromStr,
+};
 
 use derive_more::Deref;
 use semver::Version;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 
-use crate::constants::DEPENDENCY_ALIASES;
-
-#[derive(Debug, Deref, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Deref, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct PackageName(pub String);
 
+/// The parts of a hashed thunk name like `React-9c8468d8-8a7220fd` or
+/// `LuauPolyfill-2fca3173-1.1.0`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct ThunkNameParts {
+    pub name: String,
+    /// Hash identifying the repository the package was built from.
+    pub source_hash: String,
+    pub revision: ThunkRevision,
+}
+
+/// What a thunk name pins a package to: a commit of its repository, or a published version.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThunkRevision {
+    Commit(String),
+    Version(Version),
+}
+
+impl ThunkNameParts {
+    /// Splits a thunk name into its parts, or returns `None` if it isn't a hashed thunk name.
+    /// Package names and prerelease versions can both contain dashes, so the source hash is
+    /// found as the first 8 character hex segment with something after it.
+    pub fn parse(thunk_name: &str) -> Option<Self> {
+        let segments = thunk_name.split('-').collect::<Vec<&str>>();
+
+        for (index, segment) in segments.iter().enumerate().skip(1) {
+            if !is_short_hash(segment) || index + 1 >= segments.len() {
+                continue;
+            }
+
+            let rest = segments[index + 1..].join("-");
+
+            let revision = if is_short_hash(&rest) {
+                ThunkRevision::Commit(rest)
+            } else if let Ok(version) = Version::from_str(&rest) {
+                ThunkRevision::Version(version)
+            } else {
+                continue;
+            };
+
+            return Some(Self {
+                name: segments[..index].join("-"),
+                source_hash: segment.to_string(),
+                revision,
+            });
+        }
+
+        None
+    }
+}
+
+impl fmt::Display for ThunkRevision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThunkRevision::Commit(commit) => write!(f, "commit {commit}"),
+            ThunkRevision::Version(version) => write!(f, "version {version}"),
+        }
+    }
+}
+
+fn is_short_hash(segment: &str) -> bool {
+    segment.len() == 8 && segment.chars().all(|c| c.is_ascii_hexdigit())
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum License {
     MIT,
     Apache2,
+    BSD2Clause,
+    BSD3Clause,
+    ISC,
+    MPL2,
+    /// Any other license named by an `SPDX-License-Identifier`, kept as its identifier.
+    Other(String),
+    /// Code Roblox marks as proprietary, which can never be extracted.
+    RobloxProprietary,
     NoLicense,
 }
 
-impl ToString for License {
-    fn to_string(&self) -> String {
+impl License {
+    pub fn from_spdx_id(id: &str) -> Self {
+        match id.to_lowercase().as_str() {
+            "mit" => License::MIT,
+            "apache-2.0" => License::Apache2,
+            "bsd-2-clause" => License::BSD2Clause,
+            "bsd-3-clause" => License::BSD3Clause,
+            "isc" => License::ISC,
+            "mpl-2.0" => License::MPL2,
+            _ => License::Other(id.to_owned()),
+        }
+    }
+
+    /// The license's SPDX identifier. Licenses SPDX has no identifier for use a `LicenseRef-`.
+    pub fn spdx_id(&self) -> &str {
         match self {
-            License::MIT => "MIT".into(),
-            License::Apache2 => "Apache 2.0".into(),
-            License::NoLicense => "Unlicensed".into(),
+            License::MIT => "MIT",
+            License::Apache2 => "Apache-2.0",
+            License::BSD2Clause => "BSD-2-Clause",
+            License::BSD3Clause => "BSD-3-Clause",
+            License::ISC => "ISC",
+            License::MPL2 => "MPL-2.0",
+            License::Other(id) => id,
+            License::RobloxProprietary => "LicenseRef-Roblox-Proprietary",
+            License::NoLicense => "LicenseRef-Unlicensed",
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+impl fmt::Display for License {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            License::MIT => write!(f, "MIT"),
+            License::Apache2 => write!(f, "Apache 2.0"),
+            License::BSD2Clause => write!(f, "BSD 2-Clause"),
+            License::BSD3Clause => write!(f, "BSD 3-Clause"),
+            License::ISC => write!(f, "ISC"),
+            License::MPL2 => write!(f, "MPL 2.0"),
+            License::Other(id) => write!(f, "{id}"),
+            License::RobloxProprietary => write!(f, "Roblox proprietary"),
+            License::NoLicense => write!(f, "Unlicensed"),
+        }
+    }
+}
+
+impl Serialize for License {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Joins licenses into an SPDX expression that requires all of them, e.g. `MIT AND Apache-2.0`.
+pub fn spdx_expression(licenses: &[License]) -> String {
+    let mut licenses = licenses.to_vec();
+    licenses.sort();
+    licenses.dedup();
+
+    licenses
+        .iter()
+        .map(License::spdx_id)
+        .collect::<Vec<&str>>()
+        .join(" AND ")
+}
+
+/// The licenses found in a single source file, and how they were found.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct FileLicense {
+    /// Licenses the file is used under. For an SPDX expression offering a choice, like
+    /// `MIT OR GPL-3.0`, only the chosen alternative.
+    pub licenses: Vec<License>,
+    /// The file's `SPDX-License-Identifier` expression as written, if it has one.
+    pub expression: Option<String>,
+    pub detected_by: LicenseDetection,
+    /// Holders named in the file's `Copyright` lines, e.g. `Roblox Corporation`.
+    pub copyright_holders: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LicenseDetection {
+    /// Named by an `SPDX-License-Identifier` comment.
+    SpdxIdentifier,
+    /// Matched the wording of a standard license header.
+    Header,
+    /// No license found, but the file is an allowed module and assumed to be MIT.
+    AllowedModule,
+    NotFound,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
 pub struct PackageMeta {
     /// Name of the package thunk that is required by other packages.
     pub thunk_name: PackageName,
+    /// `thunk_name` split into its parts, if it's a hashed thunk name.
+    pub thunk_parts: Option<ThunkNameParts>,
     /// True name of the package, found inside the `lock.toml`.
     pub true_name: String,
     /// Version of `true_name` but Wally compliant.
     pub wally_complaint_name: String,
-    /// Version of the package, as found in the `lock.toml` file.
+    /// Version the package is published under, from the `lock.toml` file unless overridden.
     pub version: Version,
+    /// Version of the package as found in the `lock.toml` file, before any override.
+    pub lock_version: Version,
+    /// Raw contents of the package's `lock.toml`, if it has one.
+    pub lock_file: Option<String>,
     /// Reference to all dependencies this package defines.
     pub dependencies: Vec<PackageName>,
     /// A map of all dependencies of this package and how they are referred to.
     pub dependency_thunk_names: BTreeMap<PackageName, String>,
+    /// Dependencies with a thunk next to the package that no source file requires.
+    pub unused_dependencies: Vec<PackageName>,
+    /// Names the package's source requires from its thunk folder that have no thunk there.
+    pub undeclared_dependencies: Vec<String>,
+    /// Requires in the package's source whose path couldn't be followed. Unused dependencies
+    /// aren't checked if there are any.
+    pub unresolved_requires: Vec<String>,
+    /// Types re-exported by the thunks pointing at this package.
+    pub thunk_exported_types: Vec<String>,
+    /// Types exported from the package's `init.lua`.
+    pub exported_types: Vec<ExportedType>,
     /// Total lines of code in the package, used for statistics.
     pub lines_of_code: usize,
     /// List of all licenses present in package source code, including NoLicense.
     pub licenses: Vec<License>,
-    /// List of all source files that do not contain a license header.
-    pub unlicensed_files: Vec<PathBuf>,
+    /// Licenses of every source file, keyed by its path relative to `package_path`.
+    pub file_licenses: BTreeMap<PathBuf, FileLicense>,
     /// System path to the original source files.
     pub package_path: PathBuf,
 }
 
+/// An empty package at version `0.0.0`, the version assumed for packages without a `lock.toml`.
+impl Default for PackageMeta {
+    fn default() -> Self {
+        Self {
+            thunk_name: PackageName(String::new()),
+            thunk_parts: None,
+            true_name: String::new(),
+            wally_complaint_name: String::new(),
+            version: Version::new(0, 0, 0),
+            lock_version: Version::new(0, 0, 0),
+            lock_file: None,
+            dependencies: Vec::new(),
+            dependency_thunk_names: BTreeMap::new(),
+            unused_dependencies: Vec::new(),
+            undeclared_dependencies: Vec::new(),
+            unresolved_requires: Vec::new(),
+            thunk_exported_types: Vec::new(),
+            exported_types: Vec::new(),
+            lines_of_code: 0,
+            licenses: Vec::new(),
+            file_licenses: BTreeMap::new(),
+            package_path: PathBuf::new(),
+        }
+    }
+}
+
 impl PackageMeta {
-    pub fn contains_unlicensed_code(&self) -> bool {
-        // Bypass license check for overwritten dependencies ONLY.
-        if DEPENDENCY_ALIASES.contains_key(self.thunk_name.as_str()) {
-            return false;
+    /// Every copyright holder named in the package's source files.
+    pub fn copyright_holders(&self) -> BTreeSet<&str> {
+        self.file_licenses
+            .values()
+            .flat_map(|file_license| &file_license.copyright_holders)
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// An `export type` declaration in a package's entry point.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct ExportedType {
+    pub name: String,
+    /// Generic parameters as declared, including defaults: `T, U = string`.
+    pub generics: Vec<String>,
+    /// Just the names of the generic parameters: `T, U`.
+    pub generic_names: Vec<String>,
+}
+
+impl ExportedType {
+    /// Declares this type again as an alias of the one exported by `module`.
+    pub fn re_export(&self, module: &str) -> String {
+        let name = &self.name;
+
+        if self.generics.is_empty() {
+            return format!("export type {name} = {module}.{name}");
         }
 
-        self.licenses.contains(&License::NoLicense)
+        format!(
+            "export type {name}<{}> = {module}.{name}<{}>",
+            self.generics.join(", "),
+            self.generic_names.join(", ")
+        )
     }
 }
 
@@ -65,6 +289,9 @@ impl PackageMeta {
 pub struct WallyLock {
     pub name: String,
     pub version: Version,
+    /// Raw contents of the `lock.toml`, `None` when the lock was inferred.
+    #[serde(skip)]
+    pub contents: Option<String>,
 }
 
 pub type WallyDependencies = BTreeMap<String, String>;
@@ -85,3 +312,82 @@ pub struct WallyConfigPackage {
     pub registry: String,
     pub realm: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(thunk_name: &str) -> (String, String, ThunkRevision) {
+        let parts = ThunkNameParts::parse(thunk_name).unwrap();
+        (parts.name, parts.source_hash, parts.revision)
+    }
+
+    #[test]
+    fn parses_commit_thunks() {
+        assert_eq!(
+            parse("React-9c8468d8-8a7220fd"),
+            (
+                "React".to_owned(),
+                "9c8468d8".to_owned(),
+                ThunkRevision::Commit("8a7220fd".to_owned())
+            )
+        );
+    }
+
+    #[test]
+    fn parses_version_thunks() {
+        assert_eq!(
+            parse("LuauPolyfill-2fca3173-1.1.0"),
+            (
+                "LuauPolyfill".to_owned(),
+                "2fca3173".to_owned(),
+                ThunkRevision::Version(Version::new(1, 1, 0))
+            )
+        );
+    }
+
+    #[test]
+    fn parses_names_with_dashes() {
+        assert_eq!(
+            parse("React-Is-9c8468d8-8a7220fd"),
+            (
+                "React-Is".to_owned(),
+                "9c8468d8".to_owned(),
+                ThunkRevision::Commit("8a7220fd".to_owned())
+            )
+        );
+    }
+
+    #[test]
+    fn parses_prerelease_versions() {
+        assert_eq!(
+            parse("RoactCompat-2fca3173-17.0.1-rc.16.1"),
+            (
+                "RoactCompat".to_owned(),
+                "2fca3173".to_owned(),
+                ThunkRevision::Version(Version::parse("17.0.1-rc.16.1").unwrap())
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_names_without_hashes() {
+        assert_eq!(ThunkNameParts::parse("React"), None);
+        assert_eq!(ThunkNameParts::parse("LuauPolyfill-1.1.0"), None);
+        assert_eq!(ThunkNameParts::parse("9c8468d8-8a7220fd"), None);
+        assert_eq!(ThunkNameParts::parse("React-9c8468d8"), None);
+        assert_eq!(ThunkNameParts::parse("React-9c8468d8-not-a-version"), None);
+    }
+
+    #[test]
+    fn displays_revisions() {
+        assert_eq!(
+            ThunkRevision::Commit("8a7220fd".to_owned()).to_string(),
+            "commit 8a7220fd"
+        );
+        assert_eq!(
+            ThunkRevision::Version(Version::new(1, 1, 0)).to_string(),
+            "version 1.1.0"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+pub mod cli;
+pub mod config;
 pub mod constants;
 pub mod domain;
 pub mod sources;
@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+
+use super::{CorePackageSource, DirectoryPackageSource};
+
+/// Locations of the `Packages` directory inside a single version of the tracker, in order of
+/// preference.
+const PACKAGES_SUBPATHS: [&str; 2] = ["LuaPackages/Packages", "ExtraContent/LuaPackages/Packages"];
+
+/// Extracts CorePackages from a local mirror of the Client Tracker, a tree of versioned
+/// directories (e.g. `0.552.0.5520412/LuaPackages/Packages`), one per Roblox release.
+#[derive(Debug)]
+pub struct ClientTrackerPackageSource {
+    tracker_path: PathBuf,
+    /// Version directory to extract from. Defaults to the newest version in the tracker.
+    version: Option<String>,
+}
+
+impl ClientTrackerPackageSource {
+    pub fn new(tracker_path: PathBuf, version: Option<String>) -> Self {
+        Self {
+            tracker_path,
+            version,
+        }
+    }
+
+    /// Lists every version directory in the tracker, oldest first.
+    pub fn versions(&self) -> anyhow::Result<Vec<String>> {
+        let tracker_path = &self.tracker_path;
+
+        let entries = fs::read_dir(tracker_path)
+            .context(format!("Failed to read Client Tracker at {tracker_path:?}"))?;
+
+        let mut versions = entries
+            .flatten()
+            .filter(|entry| entry.path().is_dir() && find_packages_path(&entry.path()).is_some())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect::<Vec<String>>();
+
+        versions.sort_by_key(|version| version_sort_key(version));
+
+        Ok(versions)
+    }
+
+    /// The version to extract from, falling back to the newest one.
+    fn resolve_version(&self) -> anyhow::Result<String> {
+        let versions = self.versions()?;
+
+        let version = match &self.version {
+            Some(version) => {
+                if !versions.contains(version) {
+                    bail!(
+                        "Version {version:?} is not in the Client Tracker. Available versions: {}",
+                        versions.join(", ")
+                    );
+                }
+
+                version
+            }
+            None => versions
+                .last()
+                .context("Client Tracker does not contain any versions with LuaPackages")?,
+        };
+
+        Ok(version.to_owned())
+    }
+}
+
+impl CorePackageSource for ClientTrackerPackageSource {
+    fn packages_path(&self) -> anyhow::Result<PathBuf> {
+        let version = self.resolve_version()?;
+
+        eprintln!("Using Client Tracker version {version}");
+
+        let packages_path = find_packages_path(&self.tracker_path.join(&version))
+            .context(format!("Version {version:?} does not contain LuaPackages"))?;
+
+        DirectoryPackageSource::new(packages_path).packages_path()
+    }
+
+    fn describe(&self) -> String {
+        match self.resolve_version() {
+            Ok(version) => format!("Client Tracker version {version}"),
+            Err(_) => format!("Client Tracker at {:?}", self.tracker_path),
+        }
+    }
+}
+
+fn find_packages_path(version_path: &Path) -> Option<PathBuf> {
+    PACKAGES_SUBPATHS
+        .iter()
+        .map(|subpath| version_path.join(subpath))
+        .find(|path| path.is_dir())
+}
+
+/// Orders versions like `0.99.0.123` before `0.100.0.45` by comparing numeric components, while
+/// still giving non-numeric tags a stable order.
+fn version_sort_key(version: &str) -> (Vec<u64>, String) {
+    let components = version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|component| component.parse().ok())
+        .collect();
+
+    (components, version.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tracker with a version directory for each of `versions`, plus one without LuaPackages.
+    fn tracker(versions: &[&str]) -> tempfile::TempDir {
+        let tracker = tempfile::tempdir().unwrap();
+
+        for (index, version) in versions.iter().enumerate() {
+            // Both layouts the tracker has used over time
+            let subpath = PACKAGES_SUBPATHS[index % PACKAGES_SUBPATHS.len()];
+            fs::create_dir_all(tracker.path().join(version).join(subpath)).unwrap();
+        }
+
+        fs::create_dir_all(tracker.path().join("0.999.0.1/Content")).unwrap();
+        fs::write(tracker.path().join("README.md"), "").unwrap();
+
+        tracker
+    }
+
+    #[test]
+    fn versions_are_sorted_numerically() {
+        let tracker = tracker(&["0.100.0.45", "0.99.0.123", "0.552.0.5520412", "0.99.0.7"]);
+        let source = ClientTrackerPackageSource::new(tracker.path().to_owned(), None);
+
+        assert_eq!(
+            source.versions().unwrap(),
+            ["0.99.0.7", "0.99.0.123", "0.100.0.45", "0.552.0.5520412"]
+        );
+    }
+
+    #[test]
+    fn newest_version_is_used_by_default() {
+        let tracker = tracker(&["0.100.0.45", "0.99.0.123"]);
+        let source = ClientTrackerPackageSource::new(tracker.path().to_owned(), None);
+
+        assert_eq!(source.resolve_version().unwrap(), "0.100.0.45");
+        assert_eq!(source.describe(), "Client Tracker version 0.100.0.45");
+    }
+
+    #[test]
+    fn unknown_versions_list_the_available_ones() {
+        let tracker = tracker(&["0.100.0.45", "0.99.0.123"]);
+        let source =
+            ClientTrackerPackageSource::new(tracker.path().to_owned(), Some("0.999.0.1".into()));
+
+        assert_eq!(
+            source.resolve_version().unwrap_err().to_string(),
+            "Version \"0.999.0.1\" is not in the Client Tracker. Available versions: 0.99.0.123, 0.100.0.45"
+        );
+    }
+}
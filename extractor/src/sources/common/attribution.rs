@@ -0,0 +1,200 @@
+//! Builds the third-party attribution report for everything an extraction would ship.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use semver::Version;
+use serde::Serialize;
+
+use crate::domain::{spdx_expression, License, PackageName};
+
+use super::extraction::collect_dependency_closure;
+use super::PackageRegistry;
+
+#[derive(Debug, Serialize)]
+pub struct AttributionReport {
+    pub root_packages: Vec<PackageName>,
+    /// Every distinct license across all packages.
+    pub licenses: Vec<LicenseSummary>,
+    pub packages: Vec<PackageAttribution>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LicenseSummary {
+    pub license: License,
+    pub spdx_id: String,
+    /// Packages with at least one file under this license.
+    pub packages: Vec<PackageName>,
+    pub files: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageAttribution {
+    pub thunk_name: PackageName,
+    /// Name the package is published under, e.g. `core-packages/react`.
+    pub name: String,
+    pub version: Version,
+    /// SPDX expression for the whole package, as written to its `wally.toml`.
+    pub license: String,
+    pub copyright_holders: Vec<String>,
+    /// Number of source files under each license, keyed by SPDX identifier.
+    pub files_per_license: BTreeMap<String, usize>,
+}
+
+/// Attribution for every root package and all of its dependencies. Banned packages are left out,
+/// since they're never extracted.
+pub fn build_attribution_report(
+    package_registry: &PackageRegistry,
+    root_packages: &[PackageName],
+) -> anyhow::Result<AttributionReport> {
+    let config = package_registry.config();
+    let packages = collect_dependency_closure(package_registry, root_packages)?;
+
+    let mut license_packages = BTreeMap::<License, BTreeSet<PackageName>>::new();
+    let mut license_files = BTreeMap::<License, usize>::new();
+    let mut attributions = Vec::new();
+
+    for (thunk_name, package_meta) in packages {
+        if config.is_banned(thunk_name) {
+            continue;
+        }
+
+        let mut files_per_license = BTreeMap::new();
+
+        for file_license in package_meta.file_licenses.values() {
+            for license in &file_license.licenses {
+                *files_per_license
+                    .entry(license.spdx_id().to_owned())
+                    .or_default() += 1;
+                *license_files.entry(license.clone()).or_default() += 1;
+
+                license_packages
+                    .entry(license.clone())
+                    .or_default()
+                    .insert(thunk_name.to_owned());
+            }
+        }
+
+        attributions.push(PackageAttribution {
+            thunk_name: thunk_name.to_owned(),
+            name: format!("core-packages/{}", package_meta.wally_complaint_name),
+            version: package_meta.version.clone(),
+            license: spdx_expression(&package_meta.licenses),
+            copyright_holders: package_meta
+                .copyright_holders()
+                .into_iter()
+                .map(str::to_owned)
+                .collect(),
+            files_per_license,
+        });
+    }
+
+    let licenses = license_packages
+        .into_iter()
+        .map(|(license, packages)| LicenseSummary {
+            spdx_id: license.spdx_id().to_owned(),
+            files: license_files.get(&license).copied().unwrap_or_default(),
+            packages: packages.into_iter().collect(),
+            license,
+        })
+        .collect();
+
+    Ok(AttributionReport {
+        root_packages: root_packages.to_vec(),
+        licenses,
+        packages: attributions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::config::ExtractorConfig;
+    use crate::domain::{FileLicense, LicenseDetection, PackageMeta};
+
+    fn package(thunk_name: &str, dependencies: &[&str], files: &[(&str, License)]) -> PackageMeta {
+        let file_licenses = files
+            .iter()
+            .map(|(file, license)| {
+                let file_license = FileLicense {
+                    licenses: vec![license.clone()],
+                    expression: None,
+                    detected_by: LicenseDetection::SpdxIdentifier,
+                    copyright_holders: vec![format!("{thunk_name} authors")],
+                };
+
+                (PathBuf::from(file), file_license)
+            })
+            .collect::<BTreeMap<PathBuf, FileLicense>>();
+
+        let mut licenses = files
+            .iter()
+            .map(|(_, license)| license.clone())
+            .collect::<Vec<License>>();
+        licenses.sort();
+        licenses.dedup();
+
+        PackageMeta {
+            thunk_name: PackageName(thunk_name.to_owned()),
+            wally_complaint_name: thunk_name.to_lowercase(),
+            dependencies: dependencies
+                .iter()
+                .map(|i| PackageName(i.to_string()))
+                .collect(),
+            licenses,
+            file_licenses,
+            ..PackageMeta::default()
+        }
+    }
+
+    #[test]
+    fn attribution_covers_dependencies_but_not_banned_packages() {
+        let config = ExtractorConfig {
+            banned_packages: vec!["Banned".into()],
+            ..ExtractorConfig::default()
+        };
+        let mut registry = PackageRegistry::new(config, "test".into(), PathBuf::new());
+        registry.add_package(package(
+            "Root",
+            &["Shared", "Banned"],
+            &[("init.lua", License::MIT), ("Heap.lua", License::Apache2)],
+        ));
+        registry.add_package(package("Shared", &[], &[("init.lua", License::MIT)]));
+        registry.add_package(package("Banned", &[], &[("init.lua", License::ISC)]));
+
+        let report =
+            build_attribution_report(&registry, &[PackageName("Root".to_owned())]).unwrap();
+
+        let packages = report
+            .packages
+            .iter()
+            .map(|package| (package.name.as_str(), package.license.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            packages,
+            [
+                ("core-packages/root", "MIT AND Apache-2.0"),
+                ("core-packages/shared", "MIT")
+            ]
+        );
+        assert_eq!(report.packages[0].copyright_holders, ["Root authors"]);
+        assert_eq!(
+            report.packages[0].files_per_license,
+            BTreeMap::from([("Apache-2.0".to_owned(), 1), ("MIT".to_owned(), 1)])
+        );
+
+        let licenses = report
+            .licenses
+            .iter()
+            .map(|summary| {
+                (
+                    summary.spdx_id.as_str(),
+                    summary.packages.len(),
+                    summary.files,
+                )
+            })
+            .collect::<Vec<(&str, usize, usize)>>();
+        assert_eq!(licenses, [("MIT", 2, 2), ("Apache-2.0", 1, 1)]);
+    }
+}
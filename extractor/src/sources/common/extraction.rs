@@ -0,0 +1,530 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use serde::Serialize;
+
+use crate::domain::{PackageMeta, PackageName};
+
+use super::extraction_lock::{
+    find_drift, find_outdated, remove_stale_packages, ExtractionLock, FileDrift,
+};
+use super::license_policy::{evaluate_package, today, warn_unused_waivers, LicenseViolation};
+use super::output::{
+    plan_packages, sync_package_plans, write_package_plans, PackagePlan, PackageSync,
+    WrittenPackage,
+};
+use super::sbom::{write_sboms, SbomFormat};
+use super::PackageRegistry;
+
+/// License status of every package the root packages pull in, across all of them.
+#[derive(Debug, Serialize)]
+pub struct LicenseReport {
+    pub root_packages: Vec<PackageName>,
+    /// Whether every violation is covered by a current waiver.
+    pub licensed: bool,
+    /// Every package with a file the license policy doesn't allow, waived or not.
+    pub packages: Vec<PackageLicenseCheck>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageLicenseCheck {
+    pub package: PackageName,
+    /// Root packages that depend on the package, directly or not.
+    pub required_by: Vec<PackageName>,
+    pub violations: Vec<LicenseViolation>,
+}
+
+impl LicenseReport {
+    /// Violations that aren't covered by a current waiver, grouped by package.
+    pub fn unwaived(&self) -> impl Iterator<Item = (&PackageLicenseCheck, &LicenseViolation)> {
+        self.packages.iter().flat_map(|check| {
+            check
+                .violations
+                .iter()
+                .filter(|violation| !violation.is_waived())
+                .map(move |violation| (check, violation))
+        })
+    }
+}
+
+/// Evaluates every package in the dependency closure of all the root packages against the license
+/// policy, so a single run shows every file that needs a rewrite or a waiver. Banned and aliased
+/// packages are never extracted, so they're skipped.
+pub fn check_root_licenses(
+    package_registry: &PackageRegistry,
+    root_packages: &[PackageName],
+) -> anyhow::Result<LicenseReport> {
+    let config = package_registry.config();
+    let today = today();
+
+    let mut required_by = BTreeMap::<&PackageName, Vec<PackageName>>::new();
+    for thunk_name in root_packages {
+        let packages =
+            collect_dependency_closure(package_registry, std::slice::from_ref(thunk_name))
+                .context(format!(
+                    "Failed to check licenses of package {thunk_name:?}"
+                ))?;
+
+        for package_name in packages.into_keys() {
+            required_by
+                .entry(package_name)
+                .or_default()
+                .push(thunk_name.to_owned());
+        }
+    }
+
+    eprintln!(
+        "Checking licenses of {} packages required by {} root packages",
+        required_by.len(),
+        root_packages.len()
+    );
+
+    let mut packages = Vec::new();
+    let mut checked = Vec::new();
+
+    for (package_name, required_by) in required_by {
+        if config.is_banned(package_name) || config.is_aliased(package_name) {
+            continue;
+        }
+
+        let package_meta = package_registry.get_package(package_name).context(format!(
+            "Package {package_name:?} does not exist in registry"
+        ))?;
+        checked.push(package_meta);
+
+        let violations = evaluate_package(package_meta, &config.license_policy, &today);
+        if violations.is_empty() {
+            continue;
+        }
+
+        packages.push(PackageLicenseCheck {
+            package: package_name.to_owned(),
+            required_by,
+            violations,
+        });
+    }
+
+    warn_unused_waivers(&config.license_policy, package_registry, &checked);
+
+    let licensed = packages
+        .iter()
+        .flat_map(|check| &check.violations)
+        .all(LicenseViolation::is_waived);
+
+    Ok(LicenseReport {
+        root_packages: root_packages.to_vec(),
+        licensed,
+        packages,
+    })
+}
+
+/// Notes every waived violation, then errors out with every violation that isn't waived, grouped
+/// by package.
+pub fn ensure_licensed(report: &LicenseReport) -> anyhow::Result<()> {
+    for check in &report.packages {
+        for violation in check.violations.iter().filter(|v| v.is_waived()) {
+            eprintln!("NOTE: {} {}", check.package.0, violation.describe());
+        }
+    }
+
+    if report.licensed {
+        return Ok(());
+    }
+
+    let mut message = String::new();
+    let mut package_count = 0;
+    let mut file_count = 0;
+    let mut current_package = None;
+
+    for (check, violation) in report.unwaived() {
+        if current_package != Some(&check.package) {
+            current_package = Some(&check.package);
+            package_count += 1;
+
+            let required_by = check
+                .required_by
+                .iter()
+                .map(|thunk_name| thunk_name.0.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ");
+            message.push_str(&format!(
+                "\n\n{} (required by {required_by})",
+                check.package.0
+            ));
+        }
+
+        file_count += 1;
+        message.push_str(&format!("\n  {}", violation.describe()));
+    }
+
+    bail!(
+        "{file_count} files in {package_count} packages contain code the license policy doesn't allow:{message}"
+    );
+}
+
+/// Works out everything that would be written for every root package and all of its
+/// dependencies, without touching the filesystem.
+pub fn plan_extraction(
+    package_registry: &PackageRegistry,
+    root_packages: &[PackageName],
+) -> anyhow::Result<Vec<PackagePlan>> {
+    // Collect all the packages we want to write back out to the modules folder
+    let packages_to_write = collect_dependency_closure(package_registry, root_packages)?;
+
+    plan_packages(&packages_to_write, package_registry).context("Failed to plan packages")
+}
+
+/// Every root package and all of its dependencies, including banned packages.
+pub fn collect_dependency_closure<'a>(
+    package_registry: &'a PackageRegistry,
+    root_packages: &'a [PackageName],
+) -> anyhow::Result<BTreeMap<&'a PackageName, &'a PackageMeta>> {
+    let mut packages = BTreeMap::new();
+    for thunk_name in root_packages {
+        write_dependencies_recursive(&mut packages, package_registry, thunk_name)?;
+    }
+
+    Ok(packages)
+}
+
+/// Writes every root package and all of its dependencies to `extract_to`, along with an
+/// extraction lock recording every file written and a bill of materials in each of `sboms`.
+pub fn extract_packages(
+    package_registry: &PackageRegistry,
+    root_packages: &[PackageName],
+    extract_to: &Path,
+    sboms: &[SbomFormat],
+) -> anyhow::Result<Vec<WrittenPackage>> {
+    let plans = plan_extraction(package_registry, root_packages)?;
+
+    let written_packages = write_package_plans(&plans, extract_to)
+        .context("Failed to write packages to output path")?;
+
+    write_extraction_lock(package_registry, root_packages, &plans, extract_to)?;
+
+    write_sboms(sboms, &plans, package_registry, root_packages, extract_to)
+        .context("Failed to write software bill of materials")?;
+
+    Ok(written_packages)
+}
+
+/// Syncs every root package and all of its dependencies into an existing `extract_to`, only
+/// touching files that changed. Packages from the last extraction that are no longer extracted
+/// are deleted.
+pub fn update_packages(
+    package_registry: &PackageRegistry,
+    root_packages: &[PackageName],
+    extract_to: &Path,
+    sboms: &[SbomFormat],
+) -> anyhow::Result<Vec<PackageSync>> {
+    let plans = plan_extraction(package_registry, root_packages)?;
+
+    // Read before it's overwritten, to find packages from the last extraction that are gone now
+    let previous_lock = ExtractionLock::read_existing(extract_to)?;
+
+    let mut syncs = sync_package_plans(&plans, extract_to, previous_lock.as_ref())
+        .context("Failed to sync packages to output path")?;
+
+    if let Some(previous_lock) = &previous_lock {
+        let removed = remove_stale_packages(previous_lock, &plans, extract_to)
+            .context("Failed to remove packages that are no longer extracted")?;
+        syncs.extend(removed);
+    }
+
+    write_extraction_lock(package_registry, root_packages, &plans, extract_to)?;
+
+    write_sboms(sboms, &plans, package_registry, root_packages, extract_to)
+        .context("Failed to write software bill of materials")?;
+
+    Ok(syncs)
+}
+
+/// The result of checking an output directory, see [`verify_extraction`].
+#[derive(Debug, Serialize)]
+pub struct Verification {
+    /// Files edited, deleted or added by hand since the extraction.
+    pub drift: Vec<FileDrift>,
+    /// Files a fresh extraction would write differently, because their source changed upstream.
+    pub outdated: Vec<FileDrift>,
+}
+
+/// Checks an output directory against the extraction lock it was written with, and the lock
+/// against what extracting the same root packages again would write.
+pub fn verify_extraction(
+    package_registry: &PackageRegistry,
+    output_path: &Path,
+) -> anyhow::Result<Verification> {
+    let lock = ExtractionLock::read(output_path)?;
+
+    if lock.source != package_registry.source() {
+        eprintln!(
+            "NOTE: Output was extracted from {}, comparing against {}",
+            lock.source,
+            package_registry.source()
+        );
+    }
+
+    let drift = find_drift(&lock, output_path).context("Failed to compare output directory")?;
+
+    let plans = plan_extraction(package_registry, &lock.root_packages)?;
+    let outdated = find_outdated(&lock, &plans);
+
+    Ok(Verification { drift, outdated })
+}
+
+fn write_extraction_lock(
+    package_registry: &PackageRegistry,
+    root_packages: &[PackageName],
+    plans: &[PackagePlan],
+    extract_to: &Path,
+) -> anyhow::Result<()> {
+    ExtractionLock::from_plans(plans, package_registry.source(), root_packages)
+        .and_then(|lock| lock.write(extract_to))
+        .context("Failed to write extraction lock")
+}
+
+fn write_dependencies_recursive<'a>(
+    packages_to_write: &mut BTreeMap<&'a PackageName, &'a PackageMeta>,
+    package_registry: &'a PackageRegistry,
+    thunk_name: &'a PackageName,
+) -> anyhow::Result<()> {
+    let package = package_registry
+        .get_package(thunk_name)
+        .context(format!("Package {thunk_name:?} does not exist in registry"))?;
+
+    // Inserted before recursing, so a dependency cycle leads back here and stops
+    packages_to_write.insert(thunk_name, package);
+
+    for thunk_name in &package.dependencies {
+        if packages_to_write.contains_key(thunk_name) {
+            continue;
+        }
+
+        write_dependencies_recursive(packages_to_write, package_registry, thunk_name)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use std::fs;
+
+    use super::*;
+    use crate::config::{ExtractorConfig, LicenseWaiver};
+    use crate::domain::{FileLicense, License, LicenseDetection};
+    use crate::sources::common::source_utils::SourceKind;
+
+    fn package(thunk_name: &str, dependencies: &[&str]) -> PackageMeta {
+        PackageMeta {
+            thunk_name: PackageName(thunk_name.to_string()),
+            true_name: thunk_name.to_string(),
+            dependencies: dependencies
+                .iter()
+                .map(|i| PackageName(i.to_string()))
+                .collect(),
+            ..PackageMeta::default()
+        }
+    }
+
+    fn with_license(mut package_meta: PackageMeta, file: &str, license: License) -> PackageMeta {
+        package_meta.file_licenses.insert(
+            PathBuf::from(file),
+            FileLicense {
+                licenses: vec![license],
+                expression: None,
+                detected_by: LicenseDetection::NotFound,
+                copyright_holders: Vec::new(),
+            },
+        );
+        package_meta
+    }
+
+    fn registry_with(config: ExtractorConfig, packages: Vec<PackageMeta>) -> PackageRegistry {
+        let mut registry = PackageRegistry::new(config, "test".into(), PathBuf::new());
+        for package_meta in packages {
+            registry.add_package(package_meta);
+        }
+
+        registry
+    }
+
+    fn registry(packages: &[(&str, &[&str])]) -> PackageRegistry {
+        registry_with(
+            ExtractorConfig::default(),
+            packages
+                .iter()
+                .map(|(thunk_name, dependencies)| package(thunk_name, dependencies))
+                .collect(),
+        )
+    }
+
+    fn names(names: &[&str]) -> Vec<PackageName> {
+        names.iter().map(|i| PackageName(i.to_string())).collect()
+    }
+
+    fn closure(registry: &PackageRegistry, root_packages: &[&str]) -> anyhow::Result<Vec<String>> {
+        Ok(collect_dependency_closure(registry, &names(root_packages))?
+            .into_keys()
+            .map(|i| i.0.clone())
+            .collect())
+    }
+
+    #[test]
+    fn dependency_closure_stops_at_cycles() {
+        let registry = registry(&[("A", &["B"]), ("B", &["C"]), ("C", &["A", "C"]), ("D", &[])]);
+
+        assert_eq!(closure(&registry, &["A"]).unwrap(), ["A", "B", "C"]);
+        assert_eq!(
+            closure(&registry, &["C", "D"]).unwrap(),
+            ["A", "B", "C", "D"]
+        );
+    }
+
+    #[test]
+    fn dependency_closure_fails_on_missing_packages() {
+        let registry = registry(&[("A", &["B"])]);
+
+        assert!(closure(&registry, &["A"]).is_err());
+    }
+
+    /// `RootA` and `RootB` share an unlicensed dependency, and `RootA` depends on a banned one.
+    fn unlicensed_registry(waivers: Vec<LicenseWaiver>) -> PackageRegistry {
+        let mut config = ExtractorConfig {
+            banned_packages: vec!["Banned".into()],
+            dependency_aliases: BTreeMap::new(),
+            ..ExtractorConfig::default()
+        };
+        config.license_policy.waivers = waivers;
+
+        registry_with(
+            config,
+            vec![
+                with_license(
+                    package("RootA", &["Shared", "Banned"]),
+                    "init.lua",
+                    License::MIT,
+                ),
+                with_license(package("RootB", &["Shared"]), "init.lua", License::MIT),
+                with_license(package("Shared", &[]), "Heap.lua", License::NoLicense),
+                with_license(package("Banned", &[]), "init.lua", License::NoLicense),
+            ],
+        )
+    }
+
+    #[test]
+    fn license_reports_cover_every_root_package_at_once() {
+        let registry = unlicensed_registry(Vec::new());
+        let report = check_root_licenses(&registry, &names(&["RootA", "RootB"])).unwrap();
+
+        assert!(!report.licensed);
+        assert_eq!(report.packages.len(), 1);
+        assert_eq!(report.packages[0].package.0, "Shared");
+        assert_eq!(report.packages[0].required_by, names(&["RootA", "RootB"]));
+
+        let error = ensure_licensed(&report).unwrap_err().to_string();
+        assert!(error.starts_with("1 files in 1 packages"));
+        assert!(error.contains(
+            "Shared (required by RootA, RootB)\n  Heap.lua: LicenseRef-Unlicensed is not allowed"
+        ));
+    }
+
+    #[test]
+    fn waived_license_reports_are_licensed() {
+        let registry = unlicensed_registry(vec![LicenseWaiver {
+            package: "Shared".into(),
+            file: None,
+            licenses: Vec::new(),
+            reason: "Rewrite tracked separately".into(),
+            expires: "2099-01-01".into(),
+        }]);
+        let report = check_root_licenses(&registry, &names(&["RootB"])).unwrap();
+
+        assert!(report.licensed);
+        assert_eq!(report.unwaived().count(), 0);
+        assert!(ensure_licensed(&report).is_ok());
+    }
+
+    #[test]
+    fn extractions_are_planned_without_an_output_directory() {
+        let packages = tempfile::tempdir().unwrap();
+        for (file, contents) in [
+            ("Root/init.lua", "return require(script.Parent.Shared)\n"),
+            ("Root/Forked.lua", "return 'unlicensed'\n"),
+            ("Root/Tiny.lua", "return 1\n"),
+            ("Shared/init.lua", "return {}\n"),
+            ("Banned/init.lua", "return {}\n"),
+        ] {
+            let path = packages.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let config = ExtractorConfig {
+            banned_packages: vec!["Banned".into()],
+            dependency_aliases: BTreeMap::new(),
+            allowed_modules: vec!["Root/Tiny.lua".into()],
+            source_replacements: BTreeMap::from([(
+                "Root/Forked.lua".into(),
+                "return 'rewritten'\n".into(),
+            )]),
+            ..ExtractorConfig::default()
+        };
+
+        let package = |thunk_name: &str, dependencies: &[&str]| PackageMeta {
+            wally_complaint_name: thunk_name.to_lowercase(),
+            dependency_thunk_names: dependencies
+                .iter()
+                .map(|i| (PackageName(i.to_string()), i.to_string()))
+                .collect(),
+            package_path: packages.path().join(thunk_name),
+            ..package(thunk_name, dependencies)
+        };
+        let registry = registry_with(
+            config,
+            vec![
+                package("Root", &["Shared"]),
+                package("Shared", &[]),
+                package("Banned", &[]),
+            ],
+        );
+
+        let plans = plan_extraction(&registry, &names(&["Root", "Banned"])).unwrap();
+
+        let folders = plans
+            .iter()
+            .map(|plan| plan.folder.to_str().unwrap())
+            .collect::<Vec<&str>>();
+        assert_eq!(folders, ["Root", "Shared"]);
+
+        let root_files = plans[0]
+            .files
+            .iter()
+            .map(|(path, file)| (path.to_str().unwrap(), file.kind))
+            .collect::<Vec<(&str, SourceKind)>>();
+        assert_eq!(
+            root_files,
+            [
+                ("NOTICE", SourceKind::Generated),
+                ("default.project.json", SourceKind::Generated),
+                ("provenance.json", SourceKind::Generated),
+                ("src/Forked.lua", SourceKind::Replaced),
+                ("src/Tiny.lua", SourceKind::Allowed),
+                ("src/init.lua", SourceKind::Original),
+                ("wally.toml", SourceKind::Generated),
+            ]
+        );
+
+        let root = &plans[0].files;
+        assert_eq!(
+            root[Path::new("src/Forked.lua")].contents,
+            "return 'rewritten'\n"
+        );
+        assert!(root[Path::new("wally.toml")]
+            .contents
+            .contains("Shared = 'core-packages/shared@0.0.0'"));
+    }
+}
@@ -0,0 +1,421 @@
+//! The extraction lock, written to the root of the output directory to record exactly what the
+//! extractor wrote. Anything that doesn't match it was changed by hand.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::domain::PackageName;
+
+use super::{
+    output::{PackagePlan, PackageSync},
+    source_utils::sha256_hex,
+};
+
+pub const EXTRACTION_LOCK_FILE_NAME: &str = "extractor.lock";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExtractionLock {
+    /// Where CorePackages were read from, see `CorePackageSource::describe`.
+    pub source: String,
+    pub root_packages: Vec<PackageName>,
+    /// Every extracted package, keyed by its folder in the output directory.
+    pub packages: BTreeMap<String, LockedPackage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LockedPackage {
+    pub thunk_name: PackageName,
+    /// Every file written for the package, keyed by its path relative to the package folder.
+    pub files: BTreeMap<String, LockedFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LockedFile {
+    /// SHA-256 of the file as written.
+    pub sha256: String,
+    /// SHA-256 of the source file it was read from, if it wasn't generated.
+    pub original_sha256: Option<String>,
+}
+
+impl ExtractionLock {
+    pub fn from_plans(
+        plans: &[PackagePlan],
+        source: &str,
+        root_packages: &[PackageName],
+    ) -> anyhow::Result<Self> {
+        let mut packages = BTreeMap::new();
+
+        for plan in plans {
+            let mut files = BTreeMap::new();
+
+            for (relative_path, file) in &plan.files {
+                let original_sha256 = match &file.original_path {
+                    Some(original_path) => {
+                        let original = fs::read(original_path)
+                            .context(format!("Failed to read path {original_path:?}"))?;
+                        Some(sha256_hex(&original))
+                    }
+                    None => None,
+                };
+
+                files.insert(
+                    lock_key(relative_path),
+                    LockedFile {
+                        sha256: sha256_hex(file.contents.as_bytes()),
+                        original_sha256,
+                    },
+                );
+            }
+
+            packages.insert(
+                lock_key(&plan.folder),
+                LockedPackage {
+                    thunk_name: plan.thunk_name.to_owned(),
+                    files,
+                },
+            );
+        }
+
+        Ok(Self {
+            source: source.to_owned(),
+            root_packages: root_packages.to_vec(),
+            packages,
+        })
+    }
+
+    /// Reads the lock from an output directory, or `None` if nothing was extracted there yet.
+    pub fn read_existing(output_path: &Path) -> anyhow::Result<Option<Self>> {
+        if !output_path.join(EXTRACTION_LOCK_FILE_NAME).is_file() {
+            return Ok(None);
+        }
+
+        Self::read(output_path).map(Some)
+    }
+
+    pub fn read(output_path: &Path) -> anyhow::Result<Self> {
+        let path = output_path.join(EXTRACTION_LOCK_FILE_NAME);
+        let contents = fs::read_to_string(&path)
+            .context(format!("Failed to read extraction lock {path:?}"))?;
+
+        toml::from_str(&contents).context(format!("Failed to parse extraction lock {path:?}"))
+    }
+
+    pub fn write(&self, output_path: &Path) -> anyhow::Result<()> {
+        let path = output_path.join(EXTRACTION_LOCK_FILE_NAME);
+
+        let mut contents = String::from(
+            "# Written by the extractor, do not edit. Run `extractor verify` to check the output\n# directory against it.\n\n",
+        );
+        contents.push_str(&toml::to_string_pretty(self)?);
+
+        fs::write(&path, contents).context(format!("Failed to write to path {path:?}"))
+    }
+}
+
+/// A file in the output directory that doesn't match the extraction lock or a fresh extraction.
+#[derive(Debug, Serialize)]
+pub struct FileDrift {
+    /// Path of the file relative to the output directory.
+    pub path: PathBuf,
+    pub kind: DriftKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DriftKind {
+    /// Changed since it was extracted.
+    Edited,
+    /// Extracted, but deleted since.
+    Missing,
+    /// Added to a package folder by hand.
+    Untracked,
+    /// A fresh extraction would write something different, or not write it at all.
+    Outdated,
+}
+
+/// Compares the output directory against the lock it was extracted with, finding files that were
+/// edited, deleted or added by hand. Only the lock is compared against, so upstream changes can't
+/// be mistaken for hand edits, see [`find_outdated`] for those.
+pub fn find_drift(lock: &ExtractionLock, output_path: &Path) -> anyhow::Result<Vec<FileDrift>> {
+    let locked = locked_files(lock);
+
+    // Everything in a package folder, whether or not it was ever extracted
+    let mut paths = locked.keys().cloned().collect::<BTreeSet<PathBuf>>();
+
+    for folder in lock.packages.keys() {
+        let files = WalkDir::new(output_path.join(folder))
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file());
+
+        for entry in files {
+            paths.insert(entry.path().strip_prefix(output_path)?.to_owned());
+        }
+    }
+
+    let mut drift = Vec::new();
+
+    for path in paths {
+        let full_path = output_path.join(&path);
+
+        let existing = if full_path.is_file() {
+            let contents =
+                fs::read(&full_path).context(format!("Failed to read path {full_path:?}"))?;
+            Some(sha256_hex(&contents))
+        } else {
+            None
+        };
+
+        let kind = match (existing, locked.get(&path)) {
+            (None, Some(_)) => Some(DriftKind::Missing),
+            (Some(_), None) => Some(DriftKind::Untracked),
+            (Some(existing), Some(locked)) if &existing != *locked => Some(DriftKind::Edited),
+            _ => None,
+        };
+
+        if let Some(kind) = kind {
+            drift.push(FileDrift { path, kind });
+        }
+    }
+
+    Ok(drift)
+}
+
+/// Compares the lock against `plans`, what a fresh extraction from the same root packages would
+/// write, finding every file that changed upstream since. The output directory isn't read.
+pub fn find_outdated(lock: &ExtractionLock, plans: &[PackagePlan]) -> Vec<FileDrift> {
+    let locked = locked_files(lock);
+
+    let planned = plans
+        .iter()
+        .flat_map(|plan| {
+            plan.files.iter().map(|(relative_path, file)| {
+                (
+                    plan.folder.join(relative_path),
+                    sha256_hex(file.contents.as_bytes()),
+                )
+            })
+        })
+        .collect::<BTreeMap<PathBuf, String>>();
+
+    let paths = locked
+        .keys()
+        .chain(planned.keys())
+        .cloned()
+        .collect::<BTreeSet<PathBuf>>();
+
+    paths
+        .into_iter()
+        .filter(|path| locked.get(path).copied() != planned.get(path))
+        .map(|path| FileDrift {
+            path,
+            kind: DriftKind::Outdated,
+        })
+        .collect()
+}
+
+/// The SHA-256 of every file in the lock, keyed by its path relative to the output directory.
+fn locked_files(lock: &ExtractionLock) -> BTreeMap<PathBuf, &String> {
+    let mut locked = BTreeMap::new();
+
+    for (folder, package) in &lock.packages {
+        for (relative_path, file) in &package.files {
+            locked.insert(Path::new(folder).join(relative_path), &file.sha256);
+        }
+    }
+
+    locked
+}
+
+/// Deletes the folder of every package in the lock that isn't in `plans` any more, like a
+/// dependency that was dropped upstream or a package that was banned since.
+pub fn remove_stale_packages(
+    lock: &ExtractionLock,
+    plans: &[PackagePlan],
+    output_path: &Path,
+) -> anyhow::Result<Vec<PackageSync>> {
+    let planned_folders = plans
+        .iter()
+        .map(|plan| lock_key(&plan.folder))
+        .collect::<BTreeSet<String>>();
+
+    let mut syncs = Vec::new();
+
+    for (folder, package) in &lock.packages {
+        let path = output_path.join(folder);
+        if planned_folders.contains(folder) || !path.is_dir() {
+            continue;
+        }
+
+        let deleted = WalkDir::new(&path)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.into_path())
+            .collect();
+
+        fs::remove_dir_all(&path).context(format!("Failed to delete path {path:?}"))?;
+
+        eprintln!(
+            "Removed package {} which is no longer extracted",
+            package.thunk_name.0
+        );
+
+        syncs.push(PackageSync {
+            thunk_name: package.thunk_name.to_owned(),
+            path,
+            created: Vec::new(),
+            updated: Vec::new(),
+            deleted,
+            migrated_from: Vec::new(),
+            removed: true,
+            unchanged: 0,
+        });
+    }
+
+    Ok(syncs)
+}
+
+/// Relative paths are always written with forward slashes, so locks are the same on every platform.
+fn lock_key(path: &Path) -> String {
+    path.iter()
+        .map(|component| component.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sources::common::output::{write_package_plans, PlannedFile};
+    use crate::sources::common::source_utils::SourceKind;
+
+    use super::*;
+
+    fn plan(files: &[(&str, &str)]) -> PackagePlan {
+        PackagePlan {
+            thunk_name: PackageName("React-9c8468d8-8a7220fd".to_owned()),
+            folder: PathBuf::from("React"),
+            files: files
+                .iter()
+                .map(|(path, contents)| {
+                    (
+                        PathBuf::from(path),
+                        PlannedFile {
+                            kind: SourceKind::Generated,
+                            original_path: None,
+                            contents: contents.to_string(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn kinds(drift: &[FileDrift]) -> Vec<(&str, DriftKind)> {
+        drift
+            .iter()
+            .map(|file_drift| (file_drift.path.to_str().unwrap(), file_drift.kind))
+            .collect()
+    }
+
+    #[test]
+    fn hand_changes_are_found_against_the_lock() {
+        let output = tempfile::tempdir().unwrap();
+        let plans = [plan(&[
+            ("wally.toml", "wally"),
+            ("src/init.lua", "return {}"),
+            ("src/Edited.lua", "return 1"),
+        ])];
+
+        write_package_plans(&plans, output.path()).unwrap();
+        let lock = ExtractionLock::from_plans(&plans, "test", &[]).unwrap();
+
+        assert!(find_drift(&lock, output.path()).unwrap().is_empty());
+
+        let package = output.path().join("React");
+        fs::write(package.join("src/Edited.lua"), "return 2").unwrap();
+        fs::remove_file(package.join("src/init.lua")).unwrap();
+        fs::write(package.join("src/Added.lua"), "return 3").unwrap();
+
+        assert_eq!(
+            kinds(&find_drift(&lock, output.path()).unwrap()),
+            vec![
+                ("React/src/Added.lua", DriftKind::Untracked),
+                ("React/src/Edited.lua", DriftKind::Edited),
+                ("React/src/init.lua", DriftKind::Missing),
+            ]
+        );
+    }
+
+    #[test]
+    fn upstream_changes_are_not_hand_changes() {
+        let output = tempfile::tempdir().unwrap();
+        let extracted = [plan(&[
+            ("wally.toml", "wally"),
+            ("src/init.lua", "return {}"),
+            ("src/Removed.lua", "return 1"),
+        ])];
+
+        write_package_plans(&extracted, output.path()).unwrap();
+        let lock = ExtractionLock::from_plans(&extracted, "test", &[]).unwrap();
+
+        let upstream = [plan(&[
+            ("wally.toml", "wally"),
+            ("src/init.lua", "return { changed = true }"),
+            ("src/New.lua", "return 2"),
+        ])];
+
+        assert!(find_drift(&lock, output.path()).unwrap().is_empty());
+        assert_eq!(
+            kinds(&find_outdated(&lock, &upstream)),
+            vec![
+                ("React/src/New.lua", DriftKind::Outdated),
+                ("React/src/Removed.lua", DriftKind::Outdated),
+                ("React/src/init.lua", DriftKind::Outdated),
+            ]
+        );
+    }
+
+    #[test]
+    fn packages_no_longer_extracted_are_removed() {
+        let output = tempfile::tempdir().unwrap();
+        let react = plan(&[("wally.toml", "wally")]);
+        let scheduler = PackagePlan {
+            thunk_name: PackageName("Scheduler-9c8468d8-8a7220fd".to_owned()),
+            folder: PathBuf::from("Scheduler"),
+            ..plan(&[("wally.toml", "wally"), ("src/init.lua", "return {}")])
+        };
+        let extracted = [react, scheduler];
+
+        write_package_plans(&extracted, output.path()).unwrap();
+        let lock = ExtractionLock::from_plans(&extracted, "test", &[]).unwrap();
+        fs::create_dir(output.path().join("Mine")).unwrap();
+
+        let syncs = remove_stale_packages(&lock, &extracted[..1], output.path()).unwrap();
+
+        assert_eq!(syncs.len(), 1);
+        assert_eq!(syncs[0].thunk_name.0, "Scheduler-9c8468d8-8a7220fd");
+        assert!(syncs[0].removed);
+        assert_eq!(
+            syncs[0].deleted,
+            [
+                output.path().join("Scheduler/src/init.lua"),
+                output.path().join("Scheduler/wally.toml"),
+            ]
+        );
+        assert!(!output.path().join("Scheduler").exists());
+        assert!(output.path().join("React/wally.toml").is_file());
+        assert!(output.path().join("Mine").is_dir());
+    }
+}
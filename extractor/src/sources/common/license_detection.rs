@@ -0,0 +1,551 @@
+//! Works out the license of a source file from its leading comment block, either from an SPDX
+//! identifier:
+//!
+//! ```lua
+//! -- SPDX-License-Identifier: MIT AND Apache-2.0
+//! ```
+//!
+//! or from the wording of a standard license header, see
+//! [`crate::constants::LICENSE_HEADER_TEMPLATES`].
+
+use std::path::Path;
+
+use full_moon::tokenizer::TokenType;
+
+use crate::config::ExtractorConfig;
+use crate::constants::LICENSE_HEADER_TEMPLATES;
+use crate::domain::{FileLicense, License, LicenseDetection};
+
+use super::source_utils::is_script_whitelisted;
+
+const SPDX_IDENTIFIER_TAG: &str = "SPDX-License-Identifier:";
+
+/// Words that follow "copyright" in license text rather than in a copyright notice.
+const LICENSE_TEXT_AFTER_COPYRIGHT: [&str; 8] = [
+    "notice", "notices", "holder", "holders", "owner", "owners", "and", "law",
+];
+
+pub fn detect_file_license(source: &str, path: &Path, config: &ExtractorConfig) -> FileLicense {
+    let header = leading_comment_block(source);
+    let copyright_holders = copyright_holders(&header);
+
+    let expression = spdx_expression(&header);
+
+    let (licenses, detected_by) = match &expression {
+        Some(expression) => (
+            choose_alternative(parse_spdx_expression(expression), config),
+            LicenseDetection::SpdxIdentifier,
+        ),
+        None => detect_licenses(&header, path, config),
+    };
+
+    FileLicense {
+        licenses,
+        expression,
+        detected_by,
+        copyright_holders,
+    }
+}
+
+fn detect_licenses(
+    header: &str,
+    path: &Path,
+    config: &ExtractorConfig,
+) -> (Vec<License>, LicenseDetection) {
+    let licenses = match_header_templates(header);
+    if !licenses.is_empty() {
+        return (licenses, LicenseDetection::Header);
+    }
+
+    if is_script_whitelisted(path, config) {
+        return (vec![License::MIT], LicenseDetection::AllowedModule);
+    }
+
+    (vec![License::NoLicense], LicenseDetection::NotFound)
+}
+
+/// Text of every comment before the first line of code, one line per single line comment. Files
+/// that can't be tokenized fall back to [`leading_comment_lines`].
+fn leading_comment_block(source: &str) -> String {
+    let Ok(tokens) = full_moon::tokenizer::tokens(source) else {
+        return leading_comment_lines(source);
+    };
+
+    let mut header = String::new();
+
+    for token in tokens {
+        match token.token_type() {
+            TokenType::SingleLineComment { comment } => {
+                header.push_str(comment);
+                header.push('\n');
+            }
+            TokenType::MultiLineComment { comment, .. } => {
+                header.push_str(comment);
+                header.push('\n');
+            }
+            TokenType::Shebang { .. } | TokenType::Whitespace { .. } => {}
+            _ => break,
+        }
+    }
+
+    header
+}
+
+/// Comments on the lines before the first line of code, found line by line rather than with the
+/// tokenizer. Only used when the file doesn't tokenize, so a phrase later in the file, in a string
+/// or in code, still can't count as its license.
+fn leading_comment_lines(source: &str) -> String {
+    let mut header = String::new();
+    let mut in_block_comment = false;
+
+    for line in source.lines() {
+        let line = line.trim();
+
+        if in_block_comment {
+            let (comment, closed) = match line.split_once("]]") {
+                Some((comment, _)) => (comment, true),
+                None => (line, false),
+            };
+
+            header.push_str(comment);
+            header.push('\n');
+            in_block_comment = !closed;
+            continue;
+        }
+
+        if line.is_empty() || line.starts_with("#!") {
+            continue;
+        }
+
+        let Some(comment) = line.strip_prefix("--") else {
+            break;
+        };
+
+        match comment.strip_prefix("[[") {
+            Some(block) => {
+                let (comment, closed) = match block.split_once("]]") {
+                    Some((comment, _)) => (comment, true),
+                    None => (block, false),
+                };
+
+                header.push_str(comment);
+                in_block_comment = !closed;
+            }
+            None => header.push_str(comment),
+        }
+
+        header.push('\n');
+    }
+
+    header
+}
+
+/// The expression of every `SPDX-License-Identifier` line, joined with `AND` if there are several.
+fn spdx_expression(header: &str) -> Option<String> {
+    let expressions = header
+        .lines()
+        .filter_map(|line| line.split_once(SPDX_IDENTIFIER_TAG))
+        .map(|(_, expression)| expression.trim())
+        .filter(|expression| !expression.is_empty())
+        .collect::<Vec<&str>>();
+
+    match expressions.as_slice() {
+        [] => None,
+        [expression] => Some(expression.to_string()),
+        expressions => Some(
+            expressions
+                .iter()
+                .map(|expression| format!("({expression})"))
+                .collect::<Vec<String>>()
+                .join(" AND "),
+        ),
+    }
+}
+
+/// Parses an SPDX expression into its alternatives, each a set of licenses that all apply. For
+/// example `MIT OR (Apache-2.0 AND BSD-3-Clause)` gives `[[MIT], [Apache-2.0, BSD-3-Clause]]`.
+/// Exceptions like `Apache-2.0 WITH LLVM-exception` don't change the license itself, so they're
+/// dropped. Anything malformed is read as leniently as possible, since it's only a comment.
+fn parse_spdx_expression(expression: &str) -> Vec<Vec<License>> {
+    let spaced = expression.replace('(', " ( ").replace(')', " ) ");
+    let mut terms = spaced.split_whitespace().peekable();
+
+    parse_or(&mut terms)
+}
+
+type Terms<'a> = std::iter::Peekable<std::str::SplitWhitespace<'a>>;
+
+fn parse_or(terms: &mut Terms) -> Vec<Vec<License>> {
+    let mut alternatives = parse_and(terms);
+
+    while terms
+        .next_if(|term| term.eq_ignore_ascii_case("OR"))
+        .is_some()
+    {
+        alternatives.extend(parse_and(terms));
+    }
+
+    alternatives
+}
+
+fn parse_and(terms: &mut Terms) -> Vec<Vec<License>> {
+    let mut alternatives = parse_term(terms);
+
+    while terms
+        .next_if(|term| term.eq_ignore_ascii_case("AND"))
+        .is_some()
+    {
+        let right = parse_term(terms);
+
+        // Every alternative on the left combined with every alternative on the right
+        alternatives = alternatives
+            .iter()
+            .flat_map(|left| {
+                right.iter().map(move |right| {
+                    let mut licenses = left.clone();
+                    for license in right {
+                        if !licenses.contains(license) {
+                            licenses.push(license.clone());
+                        }
+                    }
+                    licenses
+                })
+            })
+            .collect();
+    }
+
+    alternatives
+}
+
+fn parse_term(terms: &mut Terms) -> Vec<Vec<License>> {
+    let alternatives = match terms.next() {
+        Some("(") => {
+            let alternatives = parse_or(terms);
+            terms.next_if_eq(&")");
+            alternatives
+        }
+        Some(id) => vec![vec![License::from_spdx_id(id)]],
+        None => Vec::new(),
+    };
+
+    if terms
+        .next_if(|term| term.eq_ignore_ascii_case("WITH"))
+        .is_some()
+    {
+        terms.next();
+    }
+
+    alternatives
+}
+
+/// The first alternative the license policy allows in full. If there's none, the first
+/// alternative, so the file fails the license check for a license it actually names.
+fn choose_alternative(alternatives: Vec<Vec<License>>, config: &ExtractorConfig) -> Vec<License> {
+    let policy = &config.license_policy;
+
+    let index = alternatives
+        .iter()
+        .position(|licenses| licenses.iter().all(|license| policy.is_allowed(license)))
+        .unwrap_or(0);
+
+    alternatives
+        .into_iter()
+        .nth(index)
+        .filter(|licenses| !licenses.is_empty())
+        .unwrap_or_else(|| vec![License::NoLicense])
+}
+
+fn match_header_templates(header: &str) -> Vec<License> {
+    // Headers are wrapped differently from file to file, so only the words themselves are compared
+    let header = normalize_whitespace(header);
+
+    let mut licenses = Vec::new();
+
+    for (phrase, license) in &LICENSE_HEADER_TEMPLATES {
+        if header.contains(&normalize_whitespace(phrase)) && !licenses.contains(license) {
+            licenses.push(license.clone());
+        }
+    }
+
+    // The BSD 3-Clause header is the 2-Clause header plus an extra clause
+    if licenses.contains(&License::BSD3Clause) {
+        licenses.retain(|license| *license != License::BSD2Clause);
+    }
+
+    licenses
+}
+
+/// Holders named in `Copyright` lines, without the years or anything after them. For example
+/// `Copyright (c) 2016-present, Facebook, Inc.` gives `Facebook, Inc`.
+fn copyright_holders(header: &str) -> Vec<String> {
+    let mut holders = Vec::new();
+
+    for line in header.lines() {
+        let line = line.trim();
+
+        // Lines like "The above copyright notice ..." are part of a license, not a notice
+        let Some(notice) = strip_prefix_ignore_case(line, "copyright") else {
+            continue;
+        };
+
+        // License text wrapped onto a line of its own, like "copyright notice and this permission
+        // notice appear in all copies" in the ISC license, or a word like "Copyrighted"
+        let first_word = notice.split_whitespace().next().unwrap_or_default();
+        if !notice.starts_with(char::is_whitespace)
+            || LICENSE_TEXT_AFTER_COPYRIGHT
+                .iter()
+                .any(|word| first_word.eq_ignore_ascii_case(word))
+        {
+            continue;
+        }
+
+        let mut holder = notice.trim_start();
+        for symbol in ["(c)", "(C)", "©"] {
+            holder = holder.trim_start_matches(symbol).trim_start();
+        }
+
+        // Years, like `2016`, `2016-2019,` or `2016-present,`
+        while let Some((first, rest)) = holder.split_once(' ') {
+            if !is_year_range(first.trim_end_matches(',')) {
+                break;
+            }
+
+            holder = rest.trim_start();
+        }
+
+        holder = strip_prefix_ignore_case(holder, "by ").unwrap_or(holder);
+
+        // Anything after the holder, sometimes on the same line
+        for trailer in ["all rights reserved", "licensed under"] {
+            if let Some(index) = holder.to_ascii_lowercase().find(trailer) {
+                holder = &holder[..index];
+            }
+        }
+
+        let holder = holder.trim().trim_end_matches(['.', ',']).trim();
+        if !holder.is_empty() && !holders.iter().any(|other| other == holder) {
+            holders.push(holder.to_owned());
+        }
+    }
+
+    holders
+}
+
+/// Whether a word is a year or a range of them, but not a name starting with a digit like `3M`.
+fn is_year_range(word: &str) -> bool {
+    let mut parts = word.split('-');
+    let is_year = |part: &str| part.len() == 4 && part.chars().all(|c| c.is_ascii_digit());
+
+    parts.next().is_some_and(is_year)
+        && parts.all(|part| is_year(part) || part.eq_ignore_ascii_case("present"))
+}
+
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &text[prefix.len()..])
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(source: &str) -> FileLicense {
+        detect_file_license(
+            source,
+            Path::new("Package/init.lua"),
+            &ExtractorConfig::default(),
+        )
+    }
+
+    #[test]
+    fn spdx_or_chooses_the_allowed_alternative() {
+        let file_license = detect("-- SPDX-License-Identifier: GPL-3.0 OR MIT\nreturn nil\n");
+
+        assert_eq!(file_license.licenses, vec![License::MIT]);
+        assert_eq!(file_license.expression.as_deref(), Some("GPL-3.0 OR MIT"));
+        assert_eq!(file_license.detected_by, LicenseDetection::SpdxIdentifier);
+    }
+
+    #[test]
+    fn spdx_or_without_an_allowed_alternative_keeps_the_first() {
+        let file_license = detect("-- SPDX-License-Identifier: GPL-3.0 OR LGPL-2.1\n");
+
+        assert_eq!(
+            file_license.licenses,
+            vec![License::Other("GPL-3.0".to_owned())]
+        );
+    }
+
+    #[test]
+    fn spdx_and_requires_every_license() {
+        let file_license = detect("-- SPDX-License-Identifier: MIT AND Apache-2.0\n");
+
+        assert_eq!(file_license.licenses, vec![License::MIT, License::Apache2]);
+    }
+
+    #[test]
+    fn spdx_with_drops_the_exception() {
+        let file_license = detect("-- SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception\n");
+
+        assert_eq!(file_license.licenses, vec![License::Apache2]);
+    }
+
+    #[test]
+    fn spdx_parentheses_group_alternatives() {
+        assert_eq!(
+            parse_spdx_expression("(GPL-3.0 OR MIT) AND (Apache-2.0 WITH LLVM-exception)"),
+            vec![
+                vec![License::Other("GPL-3.0".to_owned()), License::Apache2],
+                vec![License::MIT, License::Apache2],
+            ]
+        );
+    }
+
+    #[test]
+    fn spdx_lines_are_combined() {
+        let file_license =
+            detect("-- SPDX-License-Identifier: MIT\n-- SPDX-License-Identifier: ISC OR GPL-3.0\n");
+
+        assert_eq!(file_license.licenses, vec![License::MIT, License::ISC]);
+        assert_eq!(
+            file_license.expression.as_deref(),
+            Some("(MIT) AND (ISC OR GPL-3.0)")
+        );
+    }
+
+    #[test]
+    fn bsd_3_clause_header_is_not_also_bsd_2_clause() {
+        let header = "--[[
+            Redistribution and use in source and binary forms, with or without modification, are
+            permitted provided that the following conditions are met:
+            3. Neither the name of the copyright holder nor the names of its contributors may be
+            used to endorse or promote products derived from this software.
+        ]]";
+
+        assert_eq!(match_header_templates(header), vec![License::BSD3Clause]);
+    }
+
+    #[test]
+    fn bsd_2_clause_header() {
+        let file_license = detect(
+            "-- Redistribution and use in source and binary forms, with or without\n\
+             -- modification, are permitted provided that the following conditions are met:\n\
+             return {}\n",
+        );
+
+        assert_eq!(file_license.licenses, vec![License::BSD2Clause]);
+    }
+
+    #[test]
+    fn wrapped_header_matches() {
+        let file_license = detect(
+            "-- Copyright (c) Facebook, Inc. and its affiliates.\n\
+             --\n\
+             -- Licensed under the Apache License,\n\
+             --     Version 2.0 (the \"License\");\n\
+             return {}\n",
+        );
+
+        assert_eq!(file_license.licenses, vec![License::Apache2]);
+        assert_eq!(file_license.detected_by, LicenseDetection::Header);
+    }
+
+    #[test]
+    fn header_after_code_is_ignored() {
+        let file_license = detect("return {}\n-- Licensed under the MIT License\n");
+
+        assert_eq!(file_license.licenses, vec![License::NoLicense]);
+        assert_eq!(file_license.detected_by, LicenseDetection::NotFound);
+    }
+
+    #[test]
+    fn copyright_holders_skip_years() {
+        let header = "Copyright (c) 2016-present, Facebook, Inc.\n\
+                      Copyright 2019, 2020 Roblox Corporation. All rights reserved.\n\
+                      Copyright © 2021-2022 Jane Doe";
+
+        assert_eq!(
+            copyright_holders(header),
+            vec!["Facebook, Inc", "Roblox Corporation", "Jane Doe"]
+        );
+    }
+
+    #[test]
+    fn copyright_holders_keep_names_starting_with_digits() {
+        assert_eq!(
+            copyright_holders("Copyright (c) 2020 3M Company"),
+            vec!["3M Company"]
+        );
+        assert_eq!(
+            copyright_holders("Copyright 3M Company"),
+            vec!["3M Company"]
+        );
+    }
+
+    #[test]
+    fn copyright_holders_stop_at_license_wording() {
+        assert_eq!(
+            copyright_holders("Copyright Facebook. Licensed under the Apache License"),
+            vec!["Facebook"]
+        );
+        assert!(copyright_holders("The above copyright notice shall be included").is_empty());
+    }
+
+    #[test]
+    fn isc_header_has_a_single_holder() {
+        let file_license = detect(
+            "--[[
+                Copyright (c) 2004-2009 by Internet Systems Consortium, Inc. (\"ISC\")
+
+                Permission to use, copy, modify, and/or distribute this software for any
+                purpose with or without fee is hereby granted, provided that the above
+                copyright notice and this permission notice appear in all copies.
+
+                THE SOFTWARE IS PROVIDED \"AS IS\" AND ISC DISCLAIMS ALL WARRANTIES WITH
+                REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+                AND FITNESS. IN NO EVENT SHALL ISC BE LIABLE FOR ANY SPECIAL, DIRECT,
+                INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+                LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE
+                OR OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+                PERFORMANCE OF THIS SOFTWARE.
+            ]]
+            return {}\n",
+        );
+
+        assert_eq!(file_license.licenses, vec![License::ISC]);
+        assert_eq!(
+            file_license.copyright_holders,
+            vec!["Internet Systems Consortium, Inc. (\"ISC\")"]
+        );
+    }
+
+    #[test]
+    fn untokenizable_files_only_search_leading_comments() {
+        let source = "-- Copyright (c) 2020 Jane Doe\n\
+                      local license = \"Licensed under the Apache License, Version 2.0\"\n\
+                      local broken = \"unterminated\n";
+        let file_license = detect(source);
+
+        assert!(full_moon::tokenizer::tokens(source).is_err());
+        assert_eq!(file_license.licenses, vec![License::NoLicense]);
+        assert_eq!(file_license.copyright_holders, vec!["Jane Doe"]);
+    }
+
+    #[test]
+    fn untokenizable_files_read_block_comments() {
+        assert_eq!(
+            leading_comment_lines(
+                "#!/usr/bin/lua\n--[[ Licensed under\n  the MIT License ]]\n-- Second\nlocal x = \"\n-- Not a header"
+            ),
+            " Licensed under\nthe MIT License \n Second\n"
+        );
+    }
+}
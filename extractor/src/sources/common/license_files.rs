@@ -0,0 +1,155 @@
+//! Generates the `LICENSE` and `NOTICE` files placed at the root of every extracted package, which
+//! redistributing Apache 2.0 code requires.
+
+use crate::constants::LICENSE_TEXTS;
+use crate::domain::{License, PackageMeta};
+
+const SECTION_DIVIDER: &str =
+    "================================================================================";
+
+/// Full text of every license in the package, one after the other if there's more than one.
+/// Returns `None` if no license was found at all.
+pub fn generate_license_file(package_meta: &PackageMeta) -> Option<String> {
+    let mut licenses = package_meta
+        .licenses
+        .iter()
+        .filter(|license| **license != License::NoLicense)
+        .collect::<Vec<&License>>();
+    licenses.sort();
+
+    let copyright_notice = copyright_notice(package_meta);
+
+    // Nobody is credited rather than guessing who wrote the code
+    if copyright_notice.is_empty()
+        && licenses
+            .iter()
+            .any(|license| needs_copyright_notice(license))
+    {
+        eprintln!(
+            "WARN: No copyright holder is named in {}, its LICENSE has no copyright line",
+            package_meta.thunk_name.0
+        );
+    }
+
+    let sections = licenses
+        .iter()
+        .map(|license| license_text(license, &copyright_notice))
+        .collect::<Vec<String>>();
+
+    match sections.as_slice() {
+        [] => None,
+        [section] => Some(section.to_owned()),
+        _ => {
+            let mut contents = String::from(
+                "This package contains code under more than one license, each included in full below.\n",
+            );
+
+            for (license, section) in licenses.iter().zip(&sections) {
+                contents.push_str(&format!(
+                    "\n{SECTION_DIVIDER}\n{}\n{SECTION_DIVIDER}\n\n{section}",
+                    license.spdx_id()
+                ));
+            }
+
+            Some(contents)
+        }
+    }
+}
+
+/// Attribution for the package: where it was extracted from and every copyright holder named in
+/// its source files.
+pub fn generate_notice_file(package_meta: &PackageMeta) -> String {
+    let mut contents = format!(
+        "core-packages/{}\nExtracted from Roblox's CorePackages ({}).\n\n",
+        package_meta.wally_complaint_name, package_meta.thunk_name.0
+    );
+
+    let copyright_notice = copyright_notice(package_meta);
+    if copyright_notice.is_empty() {
+        contents.push_str("No copyright holders are named in its source files.\n");
+    } else {
+        contents.push_str(&copyright_notice);
+    }
+
+    contents
+}
+
+/// A `Copyright (c)` line for every holder named in the package, or nothing if there are none.
+fn copyright_notice(package_meta: &PackageMeta) -> String {
+    package_meta
+        .copyright_holders()
+        .iter()
+        .map(|holder| format!("Copyright (c) {holder}\n"))
+        .collect()
+}
+
+fn license_text(license: &License, copyright_notice: &str) -> String {
+    let Some(text) = LICENSE_TEXTS.get(license.spdx_id()) else {
+        return match license {
+            License::Other(id) => format!(
+                "{id}, see https://spdx.org/licenses/{id}.html for the full license text.\n"
+            ),
+            _ => format!("{license}, no license text is available.\n"),
+        };
+    };
+
+    if needs_copyright_notice(license) && !copyright_notice.is_empty() {
+        format!("{copyright_notice}\n{text}")
+    } else {
+        text.to_string()
+    }
+}
+
+/// These licenses only make sense with the copyright line they're written against.
+fn needs_copyright_notice(license: &License) -> bool {
+    matches!(
+        license,
+        License::MIT | License::BSD2Clause | License::BSD3Clause | License::ISC
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::domain::{FileLicense, LicenseDetection, PackageName};
+
+    use super::*;
+
+    fn package(copyright_holders: &[&str]) -> PackageMeta {
+        let file_license = FileLicense {
+            licenses: vec![License::MIT],
+            expression: None,
+            detected_by: LicenseDetection::Header,
+            copyright_holders: copyright_holders.iter().map(|i| i.to_string()).collect(),
+        };
+
+        PackageMeta {
+            thunk_name: PackageName("Scheduler-9c8468d8-8a7220fd".to_owned()),
+            wally_complaint_name: "scheduler".to_owned(),
+            licenses: vec![License::MIT],
+            file_licenses: [(PathBuf::from("init.lua"), file_license)].into(),
+            ..PackageMeta::default()
+        }
+    }
+
+    #[test]
+    fn license_starts_with_the_copyright_holders() {
+        let license_file = generate_license_file(&package(&["Facebook, Inc"])).unwrap();
+
+        assert!(license_file.starts_with("Copyright (c) Facebook, Inc\n\n"));
+        assert!(license_file.contains(LICENSE_TEXTS["MIT"]));
+    }
+
+    #[test]
+    fn packages_without_holders_credit_nobody() {
+        let package_meta = package(&[]);
+
+        let license_file = generate_license_file(&package_meta).unwrap();
+        assert_eq!(license_file, LICENSE_TEXTS["MIT"]);
+
+        let notice_file = generate_notice_file(&package_meta);
+        assert!(!notice_file.contains("Copyright"));
+        assert!(notice_file.ends_with("No copyright holders are named in its source files.\n"));
+    }
+}
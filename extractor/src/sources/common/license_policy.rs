@@ -0,0 +1,375 @@
+//! Evaluates packages against the license policy in the config, see
+//! [`crate::config::LicensePolicy`].
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::config::{LicensePolicy, LicenseWaiver};
+use crate::domain::{License, PackageMeta};
+
+use super::PackageRegistry;
+
+/// A license in a source file that the license policy doesn't allow.
+#[derive(Debug, Serialize)]
+pub struct LicenseViolation {
+    /// Path of the file relative to the package's source folder.
+    pub file: PathBuf,
+    /// SPDX identifier of the offending license.
+    pub license: String,
+    pub kind: ViolationKind,
+    /// The waiver covering this violation, if any. Expired waivers are still reported, but no
+    /// longer cover anything.
+    pub waiver: Option<AppliedWaiver>,
+}
+
+impl LicenseViolation {
+    pub fn is_waived(&self) -> bool {
+        self.waiver.as_ref().is_some_and(|waiver| !waiver.expired)
+    }
+
+    /// One line description, like `init.lua: LicenseRef-Unlicensed is not allowed`.
+    pub fn describe(&self) -> String {
+        let kind = match self.kind {
+            ViolationKind::Denied => "denied",
+            ViolationKind::NotAllowed => "not allowed",
+        };
+
+        let mut description = format!("{}: {} is {kind}", self.file.display(), self.license);
+
+        if let Some(waiver) = &self.waiver {
+            let status = if waiver.expired {
+                "waiver expired"
+            } else {
+                "waived until"
+            };
+            description.push_str(&format!(
+                " ({status} {}: {})",
+                waiver.expires, waiver.reason
+            ));
+        }
+
+        description
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ViolationKind {
+    /// The license is on the deny list.
+    Denied,
+    /// The license isn't on the allow list.
+    NotAllowed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AppliedWaiver {
+    pub reason: String,
+    pub expires: String,
+    pub expired: bool,
+}
+
+/// Finds every file in a package with a license the policy doesn't allow, along with the waiver
+/// covering it. A current waiver is preferred over an expired one.
+pub fn evaluate_package(
+    package_meta: &PackageMeta,
+    policy: &LicensePolicy,
+    today: &str,
+) -> Vec<LicenseViolation> {
+    find_violations(package_meta, policy)
+        .map(|(file, license, kind)| {
+            let full_path = package_meta.package_path.join(file);
+
+            let mut waivers = policy
+                .waivers
+                .iter()
+                .filter(|waiver| waiver_covers(waiver, package_meta, &full_path, license))
+                .collect::<Vec<&LicenseWaiver>>();
+            waivers.sort_by_key(|waiver| waiver.is_expired(today));
+
+            LicenseViolation {
+                file: file.to_owned(),
+                license: license.spdx_id().to_owned(),
+                kind,
+                waiver: waivers.first().map(|waiver| AppliedWaiver {
+                    reason: waiver.reason.to_owned(),
+                    expires: waiver.expires.to_owned(),
+                    expired: waiver.is_expired(today),
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Warns about waivers naming a package that isn't in the registry, and waivers that don't cover
+/// any violation in the `checked` packages they name, since both are most likely out of date.
+pub fn warn_unused_waivers(
+    policy: &LicensePolicy,
+    package_registry: &PackageRegistry,
+    checked: &[&PackageMeta],
+) {
+    for waiver in &policy.waivers {
+        let description = match &waiver.file {
+            Some(file) => format!("{:?} ({file})", waiver.package),
+            None => format!("{:?}", waiver.package),
+        };
+
+        if !package_registry
+            .packages()
+            .any(|package_meta| waiver_names(waiver, package_meta))
+        {
+            eprintln!("WARN: License waiver for {description} names an unknown package");
+            continue;
+        }
+
+        let mut named = checked
+            .iter()
+            .filter(|package_meta| waiver_names(waiver, package_meta))
+            .peekable();
+
+        if named.peek().is_none() {
+            continue;
+        }
+
+        let covers_any = named.any(|package_meta| {
+            find_violations(package_meta, policy).any(|(file, license, _)| {
+                let full_path = package_meta.package_path.join(file);
+                waiver_covers(waiver, package_meta, &full_path, license)
+            })
+        });
+
+        if !covers_any {
+            eprintln!("WARN: License waiver for {description} doesn't cover any violation");
+        }
+    }
+}
+
+/// Every license in a package the policy doesn't allow, along with the file it's in.
+fn find_violations<'a>(
+    package_meta: &'a PackageMeta,
+    policy: &'a LicensePolicy,
+) -> impl Iterator<Item = (&'a PathBuf, &'a License, ViolationKind)> {
+    package_meta
+        .file_licenses
+        .iter()
+        .flat_map(|(file, file_license)| {
+            file_license
+                .licenses
+                .iter()
+                .map(move |license| (file, license))
+        })
+        .filter_map(|(file, license)| {
+            if policy.is_denied(license) {
+                Some((file, license, ViolationKind::Denied))
+            } else if !policy.is_allowed(license) {
+                Some((file, license, ViolationKind::NotAllowed))
+            } else {
+                None
+            }
+        })
+}
+
+fn waiver_names(waiver: &LicenseWaiver, package_meta: &PackageMeta) -> bool {
+    waiver.package == package_meta.thunk_name.0 || waiver.package == package_meta.true_name
+}
+
+/// Today's date as `YYYY-MM-DD`, in UTC.
+pub fn today() -> String {
+    let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    now[..10].to_owned()
+}
+
+fn waiver_covers(
+    waiver: &LicenseWaiver,
+    package_meta: &PackageMeta,
+    full_path: &Path,
+    license: &License,
+) -> bool {
+    if !waiver_names(waiver, package_meta) {
+        return false;
+    }
+
+    // Only whole path components match, so a waiver for `Foo.lua` doesn't cover `BarFoo.lua`
+    if let Some(file) = &waiver.file {
+        let path = full_path.to_string_lossy().replace('\\', "/");
+        if path != *file && !path.ends_with(&format!("/{file}")) {
+            return false;
+        }
+    }
+
+    waiver.licenses.is_empty()
+        || waiver
+            .licenses
+            .iter()
+            .any(|id| id.eq_ignore_ascii_case(license.spdx_id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::domain::{FileLicense, LicenseDetection, PackageName};
+
+    use super::*;
+
+    fn package(files: &[(&str, License)]) -> PackageMeta {
+        PackageMeta {
+            thunk_name: PackageName("Scheduler-9c8468d8-8a7220fd".to_owned()),
+            true_name: "Scheduler".to_owned(),
+            package_path: PathBuf::from("Packages/_Index/Scheduler-9c8468d8-8a7220fd/Scheduler"),
+            file_licenses: files
+                .iter()
+                .map(|(file, license)| {
+                    (
+                        PathBuf::from(file),
+                        FileLicense {
+                            licenses: vec![license.clone()],
+                            expression: None,
+                            detected_by: LicenseDetection::NotFound,
+                            copyright_holders: Vec::new(),
+                        },
+                    )
+                })
+                .collect::<BTreeMap<PathBuf, FileLicense>>(),
+            ..PackageMeta::default()
+        }
+    }
+
+    fn waiver(file: Option<&str>, licenses: &[&str], expires: &str) -> LicenseWaiver {
+        LicenseWaiver {
+            package: "Scheduler".to_owned(),
+            file: file.map(str::to_owned),
+            licenses: licenses.iter().map(|id| id.to_string()).collect(),
+            reason: "Rewrite tracked separately".to_owned(),
+            expires: expires.to_owned(),
+        }
+    }
+
+    fn policy(waivers: Vec<LicenseWaiver>) -> LicensePolicy {
+        LicensePolicy {
+            waivers,
+            ..LicensePolicy::default()
+        }
+    }
+
+    #[test]
+    fn file_waivers_match_whole_path_components() {
+        let package_meta = package(&[
+            ("forks/Foo.lua", License::NoLicense),
+            ("forks/BarFoo.lua", License::NoLicense),
+        ]);
+        let policy = policy(vec![waiver(Some("Foo.lua"), &[], "2099-01-01")]);
+
+        let violations = evaluate_package(&package_meta, &policy, "2024-01-01");
+        let waived = violations
+            .iter()
+            .map(|violation| (violation.file.to_str().unwrap(), violation.is_waived()))
+            .collect::<Vec<(&str, bool)>>();
+
+        assert_eq!(
+            waived,
+            vec![("forks/BarFoo.lua", false), ("forks/Foo.lua", true)]
+        );
+    }
+
+    fn kinds(violations: &[LicenseViolation]) -> Vec<(&str, &str, ViolationKind)> {
+        violations
+            .iter()
+            .map(|violation| {
+                (
+                    violation.file.to_str().unwrap(),
+                    violation.license.as_str(),
+                    violation.kind,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn allowed_licenses_are_not_violations() {
+        let package_meta = package(&[("init.lua", License::MIT), ("Heap.lua", License::Apache2)]);
+
+        assert!(evaluate_package(&package_meta, &policy(Vec::new()), "2024-01-01").is_empty());
+    }
+
+    #[test]
+    fn denied_and_unlisted_licenses_are_violations() {
+        let package_meta = package(&[
+            ("init.lua", License::MIT),
+            ("Internal.lua", License::RobloxProprietary),
+            ("Fork.lua", License::Other("GPL-3.0".into())),
+            ("Heap.lua", License::NoLicense),
+        ]);
+
+        let violations = evaluate_package(&package_meta, &policy(Vec::new()), "2024-01-01");
+
+        assert_eq!(
+            kinds(&violations),
+            vec![
+                ("Fork.lua", "GPL-3.0", ViolationKind::NotAllowed),
+                (
+                    "Heap.lua",
+                    "LicenseRef-Unlicensed",
+                    ViolationKind::NotAllowed
+                ),
+                (
+                    "Internal.lua",
+                    "LicenseRef-Roblox-Proprietary",
+                    ViolationKind::Denied
+                ),
+            ]
+        );
+        assert!(violations
+            .iter()
+            .all(|violation| violation.waiver.is_none()));
+    }
+
+    #[test]
+    fn expired_waivers_are_reported_but_waive_nothing() {
+        let package_meta = package(&[("Heap.lua", License::NoLicense)]);
+        let policy = policy(vec![waiver(None, &[], "2024-01-01")]);
+
+        let on_expiry = evaluate_package(&package_meta, &policy, "2024-01-01");
+        assert!(on_expiry[0].is_waived());
+
+        let after_expiry = evaluate_package(&package_meta, &policy, "2024-01-02");
+        let waiver = after_expiry[0].waiver.as_ref().unwrap();
+        assert!(waiver.expired);
+        assert!(!after_expiry[0].is_waived());
+        assert!(after_expiry[0]
+            .describe()
+            .contains("waiver expired 2024-01-01"));
+    }
+
+    #[test]
+    fn current_waivers_are_preferred_over_expired_ones() {
+        let package_meta = package(&[("Heap.lua", License::NoLicense)]);
+        let policy = policy(vec![
+            waiver(None, &[], "2023-01-01"),
+            waiver(Some("Heap.lua"), &[], "2099-01-01"),
+        ]);
+
+        let violations = evaluate_package(&package_meta, &policy, "2024-01-01");
+
+        assert!(violations[0].is_waived());
+        assert_eq!(violations[0].waiver.as_ref().unwrap().expires, "2099-01-01");
+    }
+
+    #[test]
+    fn license_waivers_only_cover_their_licenses() {
+        let package_meta = package(&[
+            ("Heap.lua", License::NoLicense),
+            ("Internal.lua", License::RobloxProprietary),
+        ]);
+        let policy = policy(vec![waiver(None, &["licenseref-unlicensed"], "2099-01-01")]);
+
+        let violations = evaluate_package(&package_meta, &policy, "2024-01-01");
+        let waived = violations
+            .iter()
+            .map(|violation| (violation.file.to_str().unwrap(), violation.is_waived()))
+            .collect::<Vec<(&str, bool)>>();
+
+        assert_eq!(waived, vec![("Heap.lua", true), ("Internal.lua", false)]);
+    }
+}
@@ -1,9 +1,20 @@
 //! Contains common logic between all CorePackage sources.
 
+pub mod attribution;
+pub mod extraction;
+pub mod extraction_lock;
+pub mod license_detection;
+pub mod license_files;
+pub mod license_policy;
 pub mod output;
 mod package_registry;
 pub mod package_resolution;
+pub mod registry_diff;
+pub mod replacement_similarity;
+pub mod sbom;
+pub mod source_requires;
 pub mod source_utils;
 pub mod thunk_parser;
+pub mod type_exports;
 
 pub use package_registry::PackageRegistry;
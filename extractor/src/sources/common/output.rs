@@ -1,73 +1,463 @@
-use std::{collections::BTreeMap, fs, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use console::style;
+use semver::Version;
+use serde::Serialize;
 use serde_json::json;
+use walkdir::WalkDir;
 
 use crate::{
-    constants::{BANNED_PACKAGE_NAMES, DEPENDENCY_ALIASES},
-    domain::{PackageMeta, PackageName, WallyConfig, WallyConfigPackage},
+    config::ExtractorConfig,
+    domain::{
+        spdx_expression, PackageMeta, PackageName, ThunkNameParts, WallyConfig, WallyConfigPackage,
+    },
+};
+
+use super::{
+    extraction_lock::ExtractionLock,
+    license_files::{generate_license_file, generate_notice_file},
+    source_utils::{read_file_source, sha256_hex, SourceKind},
+    PackageRegistry,
 };
 
-use super::{source_utils::get_file_source, PackageRegistry};
+/// Everything that will be written for a single package, computed without touching the output
+/// directory.
+#[derive(Debug, Serialize)]
+pub struct PackagePlan {
+    pub thunk_name: PackageName,
+    /// Folder the package is written to, relative to the output directory.
+    pub folder: PathBuf,
+    /// Every file in the package, keyed by its path relative to `folder`.
+    pub files: BTreeMap<PathBuf, PlannedFile>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedFile {
+    pub kind: SourceKind,
+    /// Original source file this was read from, if it wasn't generated.
+    pub original_path: Option<PathBuf>,
+    pub contents: String,
+}
+
+/// A package written to the output directory, along with every file written for it.
+#[derive(Debug, Serialize)]
+pub struct WrittenPackage {
+    pub thunk_name: PackageName,
+    pub path: PathBuf,
+    pub files: Vec<PathBuf>,
+}
 
-pub fn output_packages_to_path(
+/// Works out the full contents of every package, including generated manifests and source
+/// replacements.
+pub fn plan_packages(
     packages: &BTreeMap<&PackageName, &PackageMeta>,
     package_registry: &PackageRegistry,
-    output_path: &PathBuf,
-) -> anyhow::Result<()> {
-    // Some terminal padding
-    println!("");
+) -> anyhow::Result<Vec<PackagePlan>> {
+    let config = package_registry.config();
+    let mut plans = Vec::new();
+
+    // Several versions of a package can be published side by side, but only under different versions
+    let mut published = BTreeMap::<(&str, &Version), &PackageName>::new();
 
+    // Packages are written to a folder named after the package, plus its version if several
+    // versions of it are being written
+    let mut folder_name_counts = BTreeMap::<&str, usize>::new();
     for (package_name, package_meta) in packages {
-        if BANNED_PACKAGE_NAMES.contains(&package_name.as_str()) {
+        if !config.is_banned(package_name) {
+            *folder_name_counts
+                .entry(package_folder_name(package_meta))
+                .or_default() += 1;
+        }
+    }
+
+    for (package_name, package_meta) in packages {
+        if config.is_banned(package_name) {
             continue;
         }
 
-        let root_folder = output_path.join(&package_name.to_string());
+        let key = (
+            package_meta.wally_complaint_name.as_str(),
+            &package_meta.version,
+        );
 
-        fs::create_dir(&root_folder).context(format!(
-            "Failed to create directory for package at path {root_folder:?}"
+        if let Some(other) = published.insert(key, package_name) {
+            bail!(
+                "Packages {:?} and {:?} would both be published as core-packages/{}@{}, add a version override for one of them",
+                other.0,
+                package_name.0,
+                package_meta.wally_complaint_name,
+                package_meta.version
+            );
+        }
+
+        let mut files = BTreeMap::new();
+
+        let wally_file = generate_wally_file(package_meta, package_registry).context(format!(
+            "Failed to generate Wally file for package {package_name:?}"
         ))?;
+        files.insert(PathBuf::from("wally.toml"), generated_file(wally_file));
 
-        write_wally_file(&root_folder, package_meta, package_registry).context(format!(
-            "Failed to write Wally file for package {package_name:?}"
+        let project_file = generate_project_file(package_meta).context(format!(
+            "Failed to generate project file for package {package_name:?}"
         ))?;
+        files.insert(
+            PathBuf::from("default.project.json"),
+            generated_file(project_file),
+        );
+
+        if !package_meta.exported_types.is_empty() {
+            files.insert(
+                PathBuf::from("thunk.lua"),
+                generated_file(generate_typed_thunk(package_meta)),
+            );
+        }
+
+        if let Some(license_file) = generate_license_file(package_meta) {
+            files.insert(PathBuf::from("LICENSE"), generated_file(license_file));
+        }
 
-        write_project_file(&root_folder, package_meta).context(format!(
-            "Failed to write project file for package {package_name:?}"
+        files.insert(
+            PathBuf::from("NOTICE"),
+            generated_file(generate_notice_file(package_meta)),
+        );
+
+        plan_source_files(package_meta, config, &mut files).context(format!(
+            "Failed to read source files for package {package_name:?}"
         ))?;
 
-        write_source_files(&root_folder, package_meta).context(format!(
-            "Failed to write source files for package {package_name:?}"
+        let provenance_file = generate_provenance_file(package_meta, package_registry, &files)
+            .context(format!(
+                "Failed to generate provenance file for package {package_name:?}"
+            ))?;
+        files.insert(
+            PathBuf::from("provenance.json"),
+            generated_file(provenance_file),
+        );
+
+        let base_name = package_folder_name(package_meta);
+        let folder = if folder_name_counts.get(base_name) > Some(&1) {
+            format!("{base_name}-{}", package_meta.version)
+        } else {
+            base_name.to_owned()
+        };
+
+        plans.push(PackagePlan {
+            thunk_name: (*package_name).to_owned(),
+            folder: PathBuf::from(folder),
+            files,
+        });
+    }
+
+    Ok(plans)
+}
+
+pub fn write_package_plans(
+    plans: &[PackagePlan],
+    output_path: &Path,
+) -> anyhow::Result<Vec<WrittenPackage>> {
+    let mut written_packages = Vec::new();
+
+    // Some terminal padding
+    eprintln!();
+
+    for plan in plans {
+        let package_name = &plan.thunk_name;
+        let root_folder = output_path.join(&plan.folder);
+
+        fs::create_dir(&root_folder).context(format!(
+            "Failed to create directory for package at path {root_folder:?}"
         ))?;
 
-        println!(
+        let mut files = Vec::new();
+
+        for (relative_path, file) in &plan.files {
+            let path = root_folder.join(relative_path);
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .context(format!("Failed to create directory at path {parent:?}"))?;
+            }
+
+            fs::write(&path, &file.contents)
+                .context(format!("Failed to write to path {path:?}"))?;
+
+            files.push(path);
+        }
+
+        eprintln!(
             "Successfully outputted package {}",
             style(&package_name.0).bold().cyan()
         );
+
+        written_packages.push(WrittenPackage {
+            thunk_name: package_name.to_owned(),
+            path: root_folder,
+            files,
+        });
+    }
+
+    Ok(written_packages)
+}
+
+/// Changes made to a single package when syncing into an existing output directory.
+#[derive(Debug, Serialize)]
+pub struct PackageSync {
+    pub thunk_name: PackageName,
+    pub path: PathBuf,
+    pub created: Vec<PathBuf>,
+    pub updated: Vec<PathBuf>,
+    pub deleted: Vec<PathBuf>,
+    /// Folders the package was previously extracted to, which were moved to `path` or removed.
+    pub migrated_from: Vec<PathBuf>,
+    /// Whether the package is no longer extracted, so its whole folder was deleted.
+    pub removed: bool,
+    /// Number of files left alone because they already matched.
+    pub unchanged: usize,
+}
+
+impl PackageSync {
+    pub fn is_unchanged(&self) -> bool {
+        !self.removed
+            && self.created.is_empty()
+            && self.updated.is_empty()
+            && self.deleted.is_empty()
+            && self.migrated_from.is_empty()
+    }
+}
+
+/// Syncs packages into an output directory that may already contain them: new packages and
+/// files are created, changed files are rewritten, files that no longer exist upstream are
+/// deleted and everything else is left untouched. Packages extracted under another folder name
+/// are moved to their planned folder first, see [`migrate_package_folders`].
+pub fn sync_package_plans(
+    plans: &[PackagePlan],
+    output_path: &Path,
+    previous_lock: Option<&ExtractionLock>,
+) -> anyhow::Result<Vec<PackageSync>> {
+    let mut migrated_folders = migrate_package_folders(plans, output_path, previous_lock)
+        .context("Failed to migrate package folders")?;
+
+    let mut syncs = Vec::new();
+
+    for plan in plans {
+        let root_folder = output_path.join(&plan.folder);
+        let mut sync = sync_package_plan(plan, &root_folder).context(format!(
+            "Failed to sync package {:?} to {root_folder:?}",
+            plan.thunk_name
+        ))?;
+
+        sync.migrated_from = migrated_folders
+            .remove(&plan.thunk_name)
+            .unwrap_or_default();
+
+        if !sync.is_unchanged() {
+            eprintln!(
+                "Updated package {}",
+                style(&plan.thunk_name.0).bold().cyan()
+            );
+        }
+
+        syncs.push(sync);
+    }
+
+    Ok(syncs)
+}
+
+fn sync_package_plan(plan: &PackagePlan, root_folder: &Path) -> anyhow::Result<PackageSync> {
+    let mut sync = PackageSync {
+        thunk_name: plan.thunk_name.to_owned(),
+        path: root_folder.to_owned(),
+        created: Vec::new(),
+        updated: Vec::new(),
+        deleted: Vec::new(),
+        migrated_from: Vec::new(),
+        removed: false,
+        unchanged: 0,
+    };
+
+    for (relative_path, file) in &plan.files {
+        let path = root_folder.join(relative_path);
+
+        let existing = if path.is_file() {
+            Some(fs::read(&path).context(format!("Failed to read path {path:?}"))?)
+        } else {
+            None
+        };
+
+        match existing {
+            Some(existing) if existing == file.contents.as_bytes() => {
+                sync.unchanged += 1;
+                continue;
+            }
+            Some(_) => sync.updated.push(path.to_owned()),
+            None => sync.created.push(path.to_owned()),
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .context(format!("Failed to create directory at path {parent:?}"))?;
+        }
+
+        fs::write(&path, &file.contents).context(format!("Failed to write to path {path:?}"))?;
+    }
+
+    // Anything left in the package that isn't part of the plan has disappeared upstream
+    let existing_files = WalkDir::new(root_folder)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file());
+
+    for entry in existing_files {
+        let path = entry.path();
+        let relative_path = path.strip_prefix(root_folder)?;
+
+        if !plan.files.contains_key(relative_path) {
+            fs::remove_file(path).context(format!("Failed to delete path {path:?}"))?;
+            sync.deleted.push(path.to_owned());
+        }
+    }
+
+    remove_empty_directories(root_folder)?;
+
+    Ok(sync)
+}
+
+/// Finds folders in the output directory holding a planned package under a different name, like
+/// `React-9c8468d8-8a7220fd/` from before folders dropped their thunk hashes, and moves each into
+/// the package's planned folder, or removes it if that folder already exists. Only folders the
+/// extractor wrote are touched: a folder belongs to a package if the previous extraction lock
+/// lists it under the package's thunk, or its `provenance.json` names the thunk. Folders that are
+/// merely named after a thunk are left alone. Returns the folders migrated for each package.
+fn migrate_package_folders(
+    plans: &[PackagePlan],
+    output_path: &Path,
+    previous_lock: Option<&ExtractionLock>,
+) -> anyhow::Result<BTreeMap<PackageName, Vec<PathBuf>>> {
+    let mut migrated_folders = BTreeMap::<PackageName, Vec<PathBuf>>::new();
+
+    if !output_path.is_dir() {
+        return Ok(migrated_folders);
+    }
+
+    let planned_folders = plans
+        .iter()
+        .map(|plan| plan.folder.as_path())
+        .collect::<BTreeSet<&Path>>();
+
+    let mut folders = fs::read_dir(output_path)
+        .context(format!("Failed to read directory {output_path:?}"))?
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| PathBuf::from(entry.file_name()))
+        .filter(|folder| !planned_folders.contains(folder.as_path()))
+        .collect::<Vec<PathBuf>>();
+    folders.sort();
+
+    for folder in folders {
+        let path = output_path.join(&folder);
+        let folder_name = folder.to_string_lossy();
+
+        let locked_thunk_name = previous_lock
+            .and_then(|lock| lock.packages.get(folder_name.as_ref()))
+            .map(|package| package.thunk_name.0.to_owned());
+
+        let Some(thunk_name) = locked_thunk_name.or_else(|| read_provenance_thunk_name(&path))
+        else {
+            if plans.iter().any(|plan| plan.thunk_name.0 == folder_name) {
+                eprintln!(
+                    "WARN: Folder {folder:?} is named after package {folder_name} but wasn't written by the extractor, leaving it alone. Move or delete it by hand if it's an old copy of the package"
+                );
+            }
+
+            continue;
+        };
+
+        let Some(plan) = plans.iter().find(|plan| plan.thunk_name.0 == thunk_name) else {
+            continue;
+        };
+
+        let target = output_path.join(&plan.folder);
+        if target.exists() {
+            fs::remove_dir_all(&path).context(format!("Failed to delete path {path:?}"))?;
+        } else {
+            fs::rename(&path, &target).context(format!("Failed to move {path:?} to {target:?}"))?;
+        }
+
+        eprintln!(
+            "Migrated package {} from {folder:?} to {:?}",
+            style(&plan.thunk_name.0).bold().cyan(),
+            plan.folder
+        );
+
+        migrated_folders
+            .entry(plan.thunk_name.to_owned())
+            .or_default()
+            .push(path);
+    }
+
+    Ok(migrated_folders)
+}
+
+fn read_provenance_thunk_name(folder: &Path) -> Option<String> {
+    let contents = fs::read_to_string(folder.join("provenance.json")).ok()?;
+    let provenance = serde_json::from_str::<serde_json::Value>(&contents).ok()?;
+
+    provenance["thunk_name"].as_str().map(str::to_owned)
+}
+
+/// Removes directories left empty by deleted files, deepest first.
+fn remove_empty_directories(root_folder: &Path) -> anyhow::Result<()> {
+    let directories = WalkDir::new(root_folder)
+        .min_depth(1)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir());
+
+    for entry in directories {
+        let path = entry.path();
+
+        if fs::read_dir(path)?.next().is_none() {
+            fs::remove_dir(path).context(format!("Failed to delete directory {path:?}"))?;
+        }
     }
 
     Ok(())
 }
 
-fn write_wally_file(
-    path: &PathBuf,
+/// Folder a package is written to: its thunk name without the hashes, e.g. `React` for
+/// `React-9c8468d8-8a7220fd`. Thunk names that aren't hashed are used as they are.
+fn package_folder_name(package_meta: &PackageMeta) -> &str {
+    match &package_meta.thunk_parts {
+        Some(parts) => &parts.name,
+        None => &package_meta.thunk_name,
+    }
+}
+
+fn generated_file(contents: String) -> PlannedFile {
+    PlannedFile {
+        kind: SourceKind::Generated,
+        original_path: None,
+        contents,
+    }
+}
+
+fn generate_wally_file(
     package_meta: &PackageMeta,
     package_registry: &PackageRegistry,
-) -> anyhow::Result<()> {
-    let package_license = package_meta
-        .licenses
-        .iter()
-        .map(|i| i.to_string())
-        .collect::<Vec<String>>()
-        .join(" + ");
+) -> anyhow::Result<String> {
+    let package_license = spdx_expression(&package_meta.licenses);
 
     let package_dependencies = package_meta
         .dependency_thunk_names
         .iter()
         .map(|(package_name, thunk_name)| {
-            if DEPENDENCY_ALIASES.contains_key(package_name) {
+            if package_registry.config().is_aliased(package_name) {
                 (package_name.to_string(), thunk_name.to_owned())
             } else {
                 let package = package_registry
@@ -79,8 +469,7 @@ fn write_wally_file(
                     thunk_name.to_owned(),
                     format!(
                         "core-packages/{}@{}",
-                        package.wally_complaint_name,
-                        package.version.to_string()
+                        package.wally_complaint_name, package.version
                     ),
                 )
             }
@@ -100,15 +489,147 @@ fn write_wally_file(
         },
     };
 
-    let source = toml::to_string_pretty(&wally_file)?;
+    let mut contents = provenance_comment(package_meta);
+    contents.push_str(&toml::to_string_pretty(&wally_file)?);
 
-    let path = path.join("wally.toml");
-    fs::write(path, source).context("Failed to write wally.toml")?;
+    Ok(contents)
+}
 
-    Ok(())
+/// Where an extracted package came from, written next to its `wally.toml` for auditing.
+#[derive(Debug, Serialize)]
+struct Provenance<'a> {
+    source: &'a str,
+    thunk_name: &'a PackageName,
+    thunk_parts: &'a Option<ThunkNameParts>,
+    /// Folder the package was read from, relative to the `Packages` directory.
+    index_folder: String,
+    lock_file: Option<toml::Value>,
+    lock_version: &'a Version,
+    published_version: &'a Version,
+    replaced_files: Vec<ReplacedFile>,
+    allowed_files: Vec<&'a Path>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReplacedFile {
+    path: PathBuf,
+    original_path: PathBuf,
+    original_sha256: String,
+    replacement_sha256: String,
+}
+
+fn generate_provenance_file(
+    package_meta: &PackageMeta,
+    package_registry: &PackageRegistry,
+    files: &BTreeMap<PathBuf, PlannedFile>,
+) -> anyhow::Result<String> {
+    let lock_file = package_meta
+        .lock_file
+        .as_deref()
+        .map(toml::from_str::<toml::Value>)
+        .transpose()
+        .context("Failed to parse lock.toml")?;
+
+    let mut replaced_files = Vec::new();
+    let mut allowed_files = Vec::new();
+
+    for (relative_path, file) in files {
+        match (file.kind, &file.original_path) {
+            (SourceKind::Replaced, Some(original_path)) => {
+                let original = fs::read(original_path)
+                    .context(format!("Failed to read path {original_path:?}"))?;
+
+                replaced_files.push(ReplacedFile {
+                    path: relative_path.to_owned(),
+                    original_path: original_path
+                        .strip_prefix(&package_meta.package_path)
+                        .unwrap_or(original_path)
+                        .to_owned(),
+                    original_sha256: sha256_hex(&original),
+                    replacement_sha256: sha256_hex(file.contents.as_bytes()),
+                });
+            }
+            (SourceKind::Allowed, _) => allowed_files.push(relative_path.as_path()),
+            _ => {}
+        }
+    }
+
+    let provenance = Provenance {
+        source: package_registry.source(),
+        thunk_name: &package_meta.thunk_name,
+        thunk_parts: &package_meta.thunk_parts,
+        index_folder: index_folder(package_meta, package_registry.packages_path()),
+        lock_file,
+        lock_version: &package_meta.lock_version,
+        published_version: &package_meta.version,
+        replaced_files,
+        allowed_files,
+    };
+
+    let mut contents = serde_json::to_string_pretty(&provenance)?;
+    contents.push('\n');
+
+    Ok(contents)
 }
 
-fn write_project_file(path: &PathBuf, package_meta: &PackageMeta) -> anyhow::Result<()> {
+/// Folder holding the package's source folder, relative to the `Packages` directory it was read
+/// from, with forward slashes on every platform.
+fn index_folder(package_meta: &PackageMeta, packages_path: &Path) -> String {
+    let index_path = package_meta
+        .package_path
+        .parent()
+        .unwrap_or(&package_meta.package_path);
+
+    index_path
+        .strip_prefix(packages_path)
+        .unwrap_or(index_path)
+        .iter()
+        .map(|component| component.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// TOML comment recording which thunk a package was extracted from.
+fn provenance_comment(package_meta: &PackageMeta) -> String {
+    let mut comment = format!(
+        "# Extracted from CorePackages thunk {}\n",
+        package_meta.thunk_name.0
+    );
+
+    if let Some(parts) = &package_meta.thunk_parts {
+        comment.push_str(&format!(
+            "# Source hash {}, {}\n",
+            parts.source_hash, parts.revision
+        ));
+    }
+
+    comment.push('\n');
+    comment
+}
+
+/// Wally's own thunks only return the package, so every exported type is lost to consumers. This
+/// is the thunk Wally would generate in `Packages/`, with the package's types re-exported.
+fn generate_typed_thunk(package_meta: &PackageMeta) -> String {
+    let name = &package_meta.wally_complaint_name;
+    let version = &package_meta.version;
+
+    let mut thunk = String::from(
+        "-- Copy over the thunk Wally generates for this package to keep its exported types.\n",
+    );
+    thunk.push_str(&format!(
+        "local REQUIRED_MODULE = require(script.Parent._Index[\"core-packages_{name}@{version}\"][\"{name}\"])\n"
+    ));
+
+    for exported_type in &package_meta.exported_types {
+        thunk.push_str(&exported_type.re_export("REQUIRED_MODULE"));
+        thunk.push('\n');
+    }
+
+    thunk.push_str("return REQUIRED_MODULE\n");
+    thunk
+}
+
+fn generate_project_file(package_meta: &PackageMeta) -> anyhow::Result<String> {
     let project = json!({
         "name": package_meta.wally_complaint_name,
         "tree": {
@@ -116,29 +637,30 @@ fn write_project_file(path: &PathBuf, package_meta: &PackageMeta) -> anyhow::Res
         }
     });
 
-    let source = serde_json::to_string_pretty(&project)?;
-
-    let path = path.join("default.project.json");
-    fs::write(path, source).context("Failed to write default.project.json")?;
-
-    Ok(())
+    Ok(serde_json::to_string_pretty(&project)?)
 }
 
-fn write_source_files(path: &PathBuf, package_meta: &PackageMeta) -> anyhow::Result<()> {
-    let root_path = path.join("src/");
-    fs::create_dir(&root_path).context("Failed to create src/ directory")?;
-
-    // Recursively step through the package's original source files and write them back here
-    // We need to do this instead of just copying the source directory because we need to
-    // manually replace the source of some files.
+fn plan_source_files(
+    package_meta: &PackageMeta,
+    config: &ExtractorConfig,
+    files: &mut BTreeMap<PathBuf, PlannedFile>,
+) -> anyhow::Result<()> {
+    // Recursively step through the package's original source files and plan to write them back
+    // under src/. We need to do this instead of just copying the source directory because we
+    // need to manually replace the source of some files.
     let package_path = &package_meta.package_path;
-    write_back_directory(&root_path, package_path)
-        .context("Failed to write back source directory")?;
+    plan_directory(Path::new("src"), package_path, config, files)
+        .context("Failed to read back source directory")?;
 
     Ok(())
 }
 
-fn write_back_directory(write_to: &PathBuf, current_path: &PathBuf) -> anyhow::Result<()> {
+fn plan_directory(
+    write_to: &Path,
+    current_path: &Path,
+    config: &ExtractorConfig,
+    files: &mut BTreeMap<PathBuf, PlannedFile>,
+) -> anyhow::Result<()> {
     let entires =
         fs::read_dir(current_path).context(format!("Failed to read directory {current_path:?}"))?;
 
@@ -153,19 +675,201 @@ fn write_back_directory(write_to: &PathBuf, current_path: &PathBuf) -> anyhow::R
         let new_path = write_to.join(file_name);
 
         if path.is_dir() {
-            fs::create_dir(&new_path)
-                .context(format!("Failed to create directory at path {new_path:?}"))?;
-
-            write_back_directory(&new_path, &path)
-                .context(format!("Failed to write back directory {path:?}"))?;
+            plan_directory(&new_path, &path, config, files)
+                .context(format!("Failed to read back directory {path:?}"))?;
         } else {
-            let content =
-                get_file_source(&path).context(format!("Failed to read path {path:?}"))?;
-
-            fs::write(&new_path, content)
-                .context(format!("Failed to write to path {new_path:?}"))?;
+            let (contents, kind) =
+                read_file_source(&path, config).context(format!("Failed to read path {path:?}"))?;
+
+            files.insert(
+                new_path,
+                PlannedFile {
+                    kind,
+                    original_path: Some(path),
+                    contents,
+                },
+            );
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ExportedType;
+
+    fn plan(thunk_name: &str, folder: &str, files: &[(&str, &str)]) -> PackagePlan {
+        PackagePlan {
+            thunk_name: PackageName(thunk_name.to_owned()),
+            folder: PathBuf::from(folder),
+            files: files
+                .iter()
+                .map(|(path, contents)| (PathBuf::from(path), generated_file(contents.to_string())))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn syncing_only_touches_changed_files() {
+        let output = tempfile::tempdir().unwrap();
+        let package = output.path().join("React");
+        fs::create_dir_all(package.join("src/Old")).unwrap();
+        fs::write(package.join("wally.toml"), "same").unwrap();
+        fs::write(package.join("src/init.lua"), "old").unwrap();
+        fs::write(package.join("src/Old/Gone.lua"), "gone").unwrap();
+
+        let plans = [plan(
+            "React-9c8468d8-8a7220fd",
+            "React",
+            &[
+                ("wally.toml", "same"),
+                ("src/init.lua", "new"),
+                ("src/New/Added.lua", "added"),
+            ],
+        )];
+        let syncs = sync_package_plans(&plans, output.path(), None).unwrap();
+
+        assert_eq!(syncs[0].created, [package.join("src/New/Added.lua")]);
+        assert_eq!(syncs[0].updated, [package.join("src/init.lua")]);
+        assert_eq!(syncs[0].deleted, [package.join("src/Old/Gone.lua")]);
+        assert_eq!(syncs[0].unchanged, 1);
+        assert!(!package.join("src/Old").exists());
+        assert_eq!(
+            fs::read_to_string(package.join("src/init.lua")).unwrap(),
+            "new"
+        );
+
+        let syncs = sync_package_plans(&plans, output.path(), None).unwrap();
+
+        assert!(syncs[0].is_unchanged());
+        assert_eq!(syncs[0].unchanged, 3);
+    }
+
+    #[test]
+    fn unrelated_folders_named_after_a_thunk_are_left_alone() {
+        let output = tempfile::tempdir().unwrap();
+        let unrelated = output.path().join("React-9c8468d8-8a7220fd");
+        fs::create_dir(&unrelated).unwrap();
+        fs::write(unrelated.join("notes.txt"), "mine").unwrap();
+
+        let plans = [plan(
+            "React-9c8468d8-8a7220fd",
+            "React",
+            &[("wally.toml", "")],
+        )];
+        let syncs = sync_package_plans(&plans, output.path(), None).unwrap();
+
+        assert!(syncs[0].migrated_from.is_empty());
+        assert_eq!(
+            fs::read_to_string(unrelated.join("notes.txt")).unwrap(),
+            "mine"
+        );
+        assert!(output.path().join("React/wally.toml").is_file());
+    }
+
+    #[test]
+    fn folders_with_provenance_are_migrated() {
+        let output = tempfile::tempdir().unwrap();
+        let old_folder = output.path().join("React-9c8468d8-8a7220fd");
+        fs::create_dir(&old_folder).unwrap();
+        fs::write(
+            old_folder.join("provenance.json"),
+            r#"{ "thunk_name": "React-9c8468d8-8a7220fd" }"#,
+        )
+        .unwrap();
+        fs::write(old_folder.join("wally.toml"), "old").unwrap();
+
+        let plans = [plan(
+            "React-9c8468d8-8a7220fd",
+            "React",
+            &[("wally.toml", "new")],
+        )];
+        let syncs = sync_package_plans(&plans, output.path(), None).unwrap();
+
+        assert_eq!(syncs[0].migrated_from, vec![old_folder.to_owned()]);
+        assert_eq!(
+            syncs[0].updated,
+            vec![output.path().join("React/wally.toml")]
+        );
+        assert_eq!(
+            syncs[0].deleted,
+            vec![output.path().join("React/provenance.json")]
+        );
+        assert!(!old_folder.exists());
+    }
+
+    #[test]
+    fn folders_in_the_previous_lock_are_migrated() {
+        let output = tempfile::tempdir().unwrap();
+        let old_folder = output.path().join("React-9c8468d8-8a7220fd");
+        fs::create_dir(&old_folder).unwrap();
+        fs::write(old_folder.join("wally.toml"), "old").unwrap();
+
+        let old_plans = [plan(
+            "React-9c8468d8-8a7220fd",
+            "React-9c8468d8-8a7220fd",
+            &[("wally.toml", "old")],
+        )];
+        let lock = ExtractionLock::from_plans(&old_plans, "test", &[]).unwrap();
+
+        let plans = [plan(
+            "React-9c8468d8-8a7220fd",
+            "React",
+            &[("wally.toml", "new")],
+        )];
+        let syncs = sync_package_plans(&plans, output.path(), Some(&lock)).unwrap();
+
+        assert_eq!(syncs[0].migrated_from, vec![old_folder.to_owned()]);
+        assert!(!old_folder.exists());
+        assert_eq!(
+            fs::read_to_string(output.path().join("React/wally.toml")).unwrap(),
+            "new"
+        );
+    }
+
+    #[test]
+    fn index_folder_is_where_the_package_was_read_from() {
+        let package_meta = PackageMeta {
+            thunk_name: PackageName("React-9c8468d8-8a7220fd".to_owned()),
+            package_path: PathBuf::from("/tmp/unpacked/Packages/_Index/React-renamed/React"),
+            ..PackageMeta::default()
+        };
+
+        assert_eq!(
+            index_folder(&package_meta, Path::new("/tmp/unpacked/Packages")),
+            "_Index/React-renamed"
+        );
+    }
+
+    #[test]
+    fn typed_thunks_re_export_every_type() {
+        let package_meta = PackageMeta {
+            wally_complaint_name: "luau-polyfill".into(),
+            version: Version::new(1, 1, 0),
+            exported_types: vec![
+                ExportedType {
+                    name: "Array".into(),
+                    generics: vec!["T = any".into()],
+                    generic_names: vec!["T".into()],
+                },
+                ExportedType {
+                    name: "Object".into(),
+                    generics: Vec::new(),
+                    generic_names: Vec::new(),
+                },
+            ],
+            ..PackageMeta::default()
+        };
+
+        assert_eq!(
+            generate_typed_thunk(&package_meta),
+            "-- Copy over the thunk Wally generates for this package to keep its exported types.\n\
+             local REQUIRED_MODULE = require(script.Parent._Index[\"core-packages_luau-polyfill@1.1.0\"][\"luau-polyfill\"])\n\
+             export type Array<T = any> = REQUIRED_MODULE.Array<T>\n\
+             export type Object = REQUIRED_MODULE.Object\n\
+             return REQUIRED_MODULE\n"
+        );
+    }
+}
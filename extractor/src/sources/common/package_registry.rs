@@ -1,29 +1,104 @@
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    path::{Path, PathBuf},
+};
 
-use anyhow::Context;
 use console::style;
 
+use crate::config::ExtractorConfig;
 use crate::domain::{PackageMeta, PackageName};
 
-#[derive(Debug)]
+use super::license_policy::{evaluate_package, today, LicenseViolation};
+use super::thunk_parser::ThunkError;
+
+#[derive(Debug, Default)]
 pub struct PackageRegistry {
     packages: BTreeMap<PackageName, PackageMeta>,
+    /// Packages exposed directly from the top level of `Packages/`, rather than only being
+    /// pulled in as a dependency.
+    root_packages: BTreeSet<PackageName>,
+    /// Thunks skipped while populating the registry, keyed by path so each is reported once.
+    thunk_errors: BTreeMap<PathBuf, ThunkError>,
+    config: ExtractorConfig,
+    /// Where the packages were loaded from, e.g. `Client Tracker version 0.552.0.5520412`.
+    source: String,
+    /// The `Packages` directory the packages were read from.
+    packages_path: PathBuf,
+    /// Name of every folder in `_Index/` a thunk points at, including banned packages.
+    index_names: BTreeSet<String>,
 }
 
 #[allow(dead_code)]
 impl PackageRegistry {
-    pub fn new() -> Self {
+    pub fn new(config: ExtractorConfig, source: String, packages_path: PathBuf) -> Self {
         Self {
             packages: BTreeMap::new(),
+            root_packages: BTreeSet::new(),
+            thunk_errors: BTreeMap::new(),
+            config,
+            source,
+            packages_path,
+            index_names: BTreeSet::new(),
         }
     }
 
+    /// Configuration the registry was populated with.
+    pub fn config(&self) -> &ExtractorConfig {
+        &self.config
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn packages_path(&self) -> &Path {
+        &self.packages_path
+    }
+
     pub fn add_package(&mut self, package: PackageMeta) {
         self.packages.insert(package.thunk_name.clone(), package);
     }
 
+    pub fn add_root_package(&mut self, package_name: PackageName) {
+        self.root_packages.insert(package_name);
+    }
+
+    pub fn add_thunk_error(&mut self, thunk_error: ThunkError) {
+        self.thunk_errors
+            .entry(thunk_error.path.clone())
+            .or_insert(thunk_error);
+    }
+
+    pub fn add_index_name(&mut self, index_name: String) {
+        self.index_names.insert(index_name);
+    }
+
+    /// Name of every folder in `_Index/` a thunk points at, whether or not it was loaded.
+    pub fn index_names(&self) -> impl Iterator<Item = &str> {
+        self.index_names.iter().map(String::as_str)
+    }
+
+    /// Every thunk that couldn't be resolved, along with why.
+    pub fn thunk_errors(&self) -> impl Iterator<Item = &ThunkError> {
+        self.thunk_errors.values()
+    }
+
+    pub fn packages(&self) -> impl Iterator<Item = &PackageMeta> {
+        self.packages.values()
+    }
+
+    pub fn root_packages(&self) -> impl Iterator<Item = &PackageMeta> {
+        self.root_packages
+            .iter()
+            .filter_map(|package_name| self.get_package(package_name))
+    }
+
+    pub fn is_root_package(&self, package_name: &PackageName) -> bool {
+        self.root_packages.contains(package_name)
+    }
+
     pub fn get_package(&self, package_name: &PackageName) -> Option<&PackageMeta> {
-        self.packages.get(&package_name)
+        self.packages.get(package_name)
     }
 
     pub fn get_package_by_display_name(
@@ -35,12 +110,91 @@ impl PackageRegistry {
             .find(|(_, meta)| meta.true_name == display_name)
     }
 
-    pub fn debug_print_packages(&self) {
+    /// Every version of a package in the index, oldest first.
+    pub fn package_versions(&self, true_name: &str) -> Vec<&PackageMeta> {
+        let mut versions = self
+            .packages()
+            .filter(|package| package.true_name == true_name)
+            .collect::<Vec<&PackageMeta>>();
+
+        versions.sort_by(|a, b| a.version.cmp(&b.version));
+        versions
+    }
+
+    /// Looks up a package by its thunk name, falling back to its true name.
+    pub fn find_package(&self, name: &str) -> Option<&PackageMeta> {
+        self.get_package(&PackageName(name.to_owned())).or_else(|| {
+            self.get_package_by_display_name(name)
+                .map(|(_, package)| package)
+        })
+    }
+
+    /// Finds the shortest chain of dependencies leading from each root package down to
+    /// `package_name`. Listing every chain instead grows exponentially on graphs as dense as
+    /// React's.
+    pub fn dependency_chains(&self, package_name: &PackageName) -> Vec<Vec<&PackageName>> {
+        self.root_packages()
+            .filter_map(|root| self.shortest_dependency_chain(&root.thunk_name, package_name))
+            .collect()
+    }
+
+    /// Breadth-first search from `from`, remembering how each package was first reached so the
+    /// chain can be walked back from `to`.
+    fn shortest_dependency_chain<'a>(
+        &'a self,
+        from: &'a PackageName,
+        to: &PackageName,
+    ) -> Option<Vec<&'a PackageName>> {
+        let mut parents = BTreeMap::<&PackageName, Option<&PackageName>>::new();
+        let mut queue = VecDeque::from([from]);
+        parents.insert(from, None);
+
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                let mut chain = vec![current];
+                while let Some(Some(parent)) = parents.get(chain.last().unwrap()) {
+                    chain.push(parent);
+                }
+
+                chain.reverse();
+                return Some(chain);
+            }
+
+            let Some(package) = self.get_package(current) else {
+                continue;
+            };
+
+            for dependency in &package.dependencies {
+                if !parents.contains_key(dependency) {
+                    parents.insert(dependency, Some(current));
+                    queue.push_back(dependency);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Files in a package the license policy doesn't allow, with the waiver covering each. Aliased
+    /// packages are swapped out for a Wally package, so none of their code is ever extracted.
+    pub fn license_violations(&self, package_meta: &PackageMeta) -> Vec<LicenseViolation> {
+        if self.config.is_aliased(&package_meta.thunk_name) {
+            return Vec::new();
+        }
+
+        evaluate_package(package_meta, &self.config.license_policy, &today())
+    }
+
+    pub fn print_packages(&self) {
         println!("Packages in index:\n");
 
         for (thunk_name, meta) in &self.packages {
-            let licensed = if !meta.contains_unlicensed_code() {
+            let violations = self.license_violations(meta);
+
+            let licensed = if violations.is_empty() {
                 style("Licensed").bold().green()
+            } else if violations.iter().all(LicenseViolation::is_waived) {
+                style("Licensed (waived)").bold().yellow()
             } else {
                 style("Unlicensed").bold().red()
             };
@@ -48,38 +202,116 @@ impl PackageRegistry {
             println!("- {} ({}) {licensed}", thunk_name.0, meta.true_name);
         }
 
-        println!(""); // Empty padding
+        println!(); // Empty padding
     }
 
-    /// Recursively checks a package and all of its dependencies for it is appropriately
-    /// licensed.
-    pub fn is_package_licensed(
-        &self,
-        package_name: &PackageName,
-    ) -> anyhow::Result<(bool, Vec<PathBuf>)> {
-        let package = self.get_package(&package_name).context(format!(
-            "Package {package_name:?} does not exist in registry"
-        ))?;
+    /// Finds dependency cycles in the registry, at least one through every package that's part of
+    /// one. Each cycle starts and ends with the same package, e.g. `[A, B, A]`.
+    pub fn dependency_cycles(&self) -> Vec<Vec<&PackageName>> {
+        let mut cycles = BTreeSet::new();
+        let mut finished = BTreeSet::new();
 
-        let unlicensed = package.contains_unlicensed_code();
-        let mut unlicensed_files = package.unlicensed_files.clone();
+        for package in self.packages() {
+            let mut stack = Vec::new();
+            self.collect_cycles(&package.thunk_name, &mut stack, &mut finished, &mut cycles);
+        }
 
-        if unlicensed {
-            return Ok((false, unlicensed_files));
+        cycles.into_iter().collect()
+    }
+
+    fn collect_cycles<'a>(
+        &'a self,
+        package_name: &'a PackageName,
+        stack: &mut Vec<&'a PackageName>,
+        finished: &mut BTreeSet<&'a PackageName>,
+        cycles: &mut BTreeSet<Vec<&'a PackageName>>,
+    ) {
+        if let Some(position) = stack.iter().position(|i| *i == package_name) {
+            let mut cycle = stack[position..].to_vec();
+
+            // Rotate so the same cycle entered from a different package compares equal
+            let smallest = (0..cycle.len()).min_by_key(|i| cycle[*i]).unwrap_or(0);
+            cycle.rotate_left(smallest);
+            cycle.push(cycle[0]);
+
+            cycles.insert(cycle);
+            return;
         }
 
+        if finished.contains(package_name) {
+            return;
+        }
+
+        let Some(package) = self.get_package(package_name) else {
+            return;
+        };
+
+        stack.push(package_name);
+
         for dependency in &package.dependencies {
-            let (licensed, unlicensed) = self
-                .is_package_licensed(dependency)
-                .context(format!("Failed to check if {dependency:?} is licensed"))?;
+            self.collect_cycles(dependency, stack, finished, cycles);
+        }
 
-            unlicensed_files.extend(unlicensed);
+        stack.pop();
+        finished.insert(package_name);
+    }
+}
 
-            if !licensed {
-                return Ok((false, unlicensed_files));
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(packages: &[(&str, &[&str])]) -> PackageRegistry {
+        let mut registry =
+            PackageRegistry::new(ExtractorConfig::default(), "test".into(), PathBuf::new());
+
+        for (thunk_name, dependencies) in packages {
+            registry.add_package(PackageMeta {
+                thunk_name: PackageName(thunk_name.to_string()),
+                dependencies: dependencies
+                    .iter()
+                    .map(|i| PackageName(i.to_string()))
+                    .collect(),
+                ..PackageMeta::default()
+            });
         }
 
-        Ok((true, unlicensed_files))
+        registry
+    }
+
+    fn cycles(registry: &PackageRegistry) -> Vec<Vec<&str>> {
+        registry
+            .dependency_cycles()
+            .into_iter()
+            .map(|cycle| cycle.into_iter().map(|i| i.0.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn two_package_cycles_are_found_once() {
+        let registry = registry(&[("A", &["B"]), ("B", &["A"])]);
+
+        assert_eq!(cycles(&registry), vec![vec!["A", "B", "A"]]);
+    }
+
+    #[test]
+    fn packages_depending_on_themselves_are_cycles() {
+        let registry = registry(&[("A", &["A", "B"]), ("B", &[])]);
+
+        assert_eq!(cycles(&registry), vec![vec!["A", "A"]]);
+    }
+
+    #[test]
+    fn cycles_reached_from_several_packages_are_found_once() {
+        let registry = registry(&[("A", &["C"]), ("B", &["D"]), ("C", &["D"]), ("D", &["C"])]);
+
+        assert_eq!(cycles(&registry), vec![vec!["C", "D", "C"]]);
+    }
+
+    #[test]
+    fn acyclic_registries_have_no_cycles() {
+        let registry = registry(&[("A", &["B", "C"]), ("B", &["C"]), ("C", &[])]);
+
+        assert!(cycles(&registry).is_empty());
     }
 }
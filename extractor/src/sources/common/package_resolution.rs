@@ -1,112 +1,331 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::OsStr;
 use std::fs::{self, DirEntry};
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::path::{Path, PathBuf};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use convert_case::{Case, Casing};
 use semver::Version;
 use walkdir::WalkDir;
 
-use crate::constants::{BANNED_PACKAGE_NAMES, DEPENDENCY_ALIASES, PACKAGE_VERSION_OVERRIDES};
-use crate::domain::{License, PackageMeta, PackageName, WallyLock};
-
-use super::source_utils::{get_file_source, infer_script_license};
-use super::thunk_parser::resolve_index_path;
+use crate::config::ExtractorConfig;
+use crate::domain::{
+    ExportedType, FileLicense, License, PackageMeta, PackageName, ThunkNameParts, ThunkRevision,
+    WallyLock,
+};
+
+use super::license_detection::detect_file_license;
+use super::source_requires::collect_package_requires;
+use super::source_utils::get_file_source;
+use super::thunk_parser::{resolve_thunk, ThunkError};
+use super::type_exports::{collect_exported_types, find_entry_point};
 use super::PackageRegistry;
 
-/// Collects all packages in the specified path and adds them to the PackageRegistry.
+/// Collects all packages in the specified path and adds them to the PackageRegistry. Thunks that
+/// can't be parsed are skipped and recorded in the registry rather than failing the whole run.
 pub fn populate_package_registry(
     package_registry: &mut PackageRegistry,
-    packages_path: &PathBuf,
+    packages_path: &Path,
 ) -> anyhow::Result<()> {
+    // Cloned up front, since the registry is mutated while the config is still in use
+    let config = package_registry.config().clone();
+
     let files = get_lua_files_in_path(packages_path)
         .context("Failed to get Lua files in Packages directory")?;
 
     let mut index_paths = BTreeMap::new();
+    let mut thunks = ThunkResolver::default();
 
     for file in files {
         let path = file.path();
 
         // Resolve where this module is pointing to in the package index
-        if let Ok((thunk_name, index_path)) = resolve_index_path(&path, packages_path) {
-            let dependencies = resolve_package_dependencies(&index_path, packages_path)
-                .context("Failed to parse dependencies")?;
+        let Some((thunk_name, index_path)) = thunks.resolve(&path, packages_path) else {
+            continue;
+        };
 
-            for (thunk_name, thunk_path) in dependencies {
-                if index_paths.contains_key(&thunk_name as &String) {
-                    continue;
-                }
+        package_registry.add_root_package(PackageName(thunk_name.clone()));
+
+        // Inserted before following dependencies, so a dependency cycle leads back here and stops
+        index_paths.insert(thunk_name, index_path.clone());
+
+        let dependencies = resolve_package_dependencies(&index_path, packages_path, &mut thunks)
+            .context("Failed to parse dependencies")?;
 
-                populate_index_paths(&mut index_paths, packages_path, thunk_path)?;
+        for (thunk_name, thunk_path) in dependencies {
+            // Banned dependencies are never extracted, so there's no need to follow them
+            if index_paths.contains_key(&thunk_name as &String) || config.is_banned(&thunk_name) {
+                continue;
             }
 
-            index_paths.insert(thunk_name, index_path);
+            populate_index_paths(
+                &mut index_paths,
+                packages_path,
+                thunk_path,
+                &config,
+                &mut thunks,
+            )?;
         }
     }
 
+    let mut package_metas = Vec::new();
+
     for (package_name, index_path) in index_paths {
-        if BANNED_PACKAGE_NAMES.contains(&&*package_name)
-            && !DEPENDENCY_ALIASES.contains_key(&&*package_name)
-        {
-            println!("WARN: Found blocked package {package_name}. Skipping.");
+        if config.is_banned(&package_name) && !config.is_aliased(&package_name) {
+            eprintln!("WARN: Found blocked package {package_name}. Skipping.");
             continue;
         }
 
-        let package_lock = parse_package_lock(&index_path).context("Failed to parse lock.toml")?;
+        let package_lock = parse_package_lock(&index_path, &package_name, &config)
+            .context("Failed to parse lock.toml")?;
         let true_name = package_lock.name.split("/").last().unwrap();
 
         // Next, work out meta information about the package (LOC, license info).
         let source_path = index_path.join(true_name);
-        let (loc, licenses, unlicensed_files) =
-            get_package_source_info(&source_path).context("Failed to get package source info")?;
+        let source_info = get_package_source_info(&source_path, &config)
+            .context("Failed to get package source info")?;
+
+        let exported_types = get_package_exported_types(&source_path, &config);
 
-        let dependencies = resolve_package_dependencies(&index_path, packages_path)
+        let dependencies = resolve_package_dependencies(&index_path, packages_path, &mut thunks)
             .context("Failed to parse dependencies")?;
 
         let dependency_thunk_names = dependencies
             .iter()
             .map(|(package_name, path)| {
-                if let Some(alias) = DEPENDENCY_ALIASES.get(package_name) {
+                if let Some(alias) = config.dependency_aliases.get(package_name.as_str()) {
                     (package_name.to_owned(), alias.to_string())
                 } else {
-                    let thunk_name = path.file_name().expect("file name").to_str().unwrap();
-                    let thunk_name = thunk_name.split(".").next().unwrap();
-
-                    (package_name.to_owned(), thunk_name.to_owned())
+                    (package_name.to_owned(), thunk_file_name(path))
                 }
             })
             .collect::<BTreeMap<PackageName, String>>();
 
-        let version = if let Some(version) = PACKAGE_VERSION_OVERRIDES.get(&package_name) {
-            Version::from_str(version).unwrap()
+        // Compare the dependency thunks sitting next to the package with what its source requires
+        let requires = collect_package_requires(&source_path, &index_path, &config)
+            .context("Failed to collect requires from package source")?;
+
+        if !requires.unparsed_files.is_empty() {
+            eprintln!(
+                "WARN: Could not parse {} source files in {package_name}, their requires are not checked",
+                requires.unparsed_files.len()
+            );
+        }
+
+        // A dependency might be required through a path that couldn't be followed
+        let unused_dependencies = if requires.is_complete() {
+            dependencies
+                .iter()
+                .filter(|(_, path)| !requires.required_names.contains(&thunk_file_name(path)))
+                .map(|(package_name, _)| package_name.to_owned())
+                .collect::<Vec<PackageName>>()
         } else {
-            package_lock.version
+            Vec::new()
         };
-        
+
+        let declared_names = dependencies
+            .values()
+            .map(|path| thunk_file_name(path))
+            .collect::<BTreeSet<String>>();
+
+        let undeclared_dependencies = requires
+            .required_names
+            .difference(&declared_names)
+            .cloned()
+            .collect::<Vec<String>>();
+
+        let version = if let Some(version) = config.version_overrides.get(&package_name) {
+            version.to_owned()
+        } else {
+            package_lock.version.clone()
+        };
+
         let package_meta = PackageMeta {
             thunk_name: PackageName(package_name.clone()),
+            thunk_parts: ThunkNameParts::parse(&package_name),
             true_name: true_name.to_owned(),
             wally_complaint_name: true_name.to_case(Case::Kebab),
             version,
+            lock_version: package_lock.version,
+            lock_file: package_lock.contents,
             dependencies: dependencies.into_keys().collect::<Vec<PackageName>>(),
             dependency_thunk_names,
-            lines_of_code: loc,
-            licenses,
-            unlicensed_files,
+            unused_dependencies,
+            undeclared_dependencies,
+            unresolved_requires: requires.unresolved_requires.into_iter().collect(),
+            thunk_exported_types: thunks
+                .exported_types
+                .get(&package_name)
+                .map(|types| types.iter().cloned().collect())
+                .unwrap_or_default(),
+            exported_types,
+            lines_of_code: source_info.lines_of_code,
+            licenses: source_info.licenses,
+            file_licenses: source_info.file_licenses,
             package_path: source_path,
         };
 
+        package_metas.push(package_meta);
+    }
+
+    redirect_banned_dependencies(&mut package_metas, &config)?;
+
+    for package_meta in package_metas {
         package_registry.add_package(package_meta);
     }
 
+    for thunk_error in thunks.errors {
+        package_registry.add_thunk_error(thunk_error);
+    }
+
+    for index_name in thunks.exported_types.into_keys() {
+        package_registry.add_index_name(index_name);
+    }
+
+    Ok(())
+}
+
+/// The `_Index` can hold several versions of one package, and old versions are usually banned.
+/// Dependencies listed in the config's dependency redirects are pointed at the version chosen
+/// there. Consumers still pointing at a banned version after that are pointed at the newest
+/// extracted version of the same package instead, matched by the name in the banned thunk name.
+/// Fails if there is no other version to point them at.
+fn redirect_banned_dependencies(
+    package_metas: &mut [PackageMeta],
+    config: &ExtractorConfig,
+) -> anyhow::Result<()> {
+    let loaded = package_metas
+        .iter()
+        .map(|package_meta| package_meta.thunk_name.clone())
+        .collect::<BTreeSet<PackageName>>();
+
+    let mut newest_versions = BTreeMap::<&str, &PackageMeta>::new();
+    for package_meta in package_metas.iter() {
+        if config.is_banned(&package_meta.thunk_name) {
+            continue;
+        }
+
+        let newest = newest_versions
+            .entry(version_group_name(package_meta))
+            .or_insert(package_meta);
+
+        if package_meta.version > newest.version {
+            *newest = package_meta;
+        }
+    }
+
+    let newest_versions = newest_versions
+        .into_iter()
+        .map(|(name, package_meta)| (name.to_owned(), package_meta.thunk_name.clone()))
+        .collect::<BTreeMap<String, PackageName>>();
+
+    for package_meta in package_metas.iter_mut() {
+        let redirects = config.dependency_redirects.get(&package_meta.thunk_name.0);
+
+        for index in 0..package_meta.dependencies.len() {
+            let dependency = &package_meta.dependencies[index];
+
+            let chosen = redirects
+                .and_then(|redirects| redirects.get(&dependency.0))
+                .map(|replacement| PackageName(replacement.to_owned()));
+
+            let replacement = match chosen {
+                Some(replacement) if loaded.contains(&replacement) => replacement,
+                Some(replacement) => {
+                    eprintln!(
+                        "WARN: Can't redirect {} of {} to {}, it isn't required by any package",
+                        dependency.0, package_meta.thunk_name.0, replacement.0
+                    );
+                    continue;
+                }
+                None if config.is_banned(dependency) && !config.is_aliased(dependency) => {
+                    let name = ThunkNameParts::parse(dependency)
+                        .map(|parts| parts.name)
+                        .unwrap_or_else(|| dependency.0.to_owned());
+
+                    let Some(replacement) = newest_versions.get(&name) else {
+                        // Banned packages are never extracted, so neither are their dependencies
+                        if config.is_banned(&package_meta.thunk_name) {
+                            continue;
+                        }
+
+                        bail!(
+                            "{} depends on banned package {}, and there is no other version of {name} to use instead. Add a dependency alias for it, or ban {} too",
+                            package_meta.thunk_name.0,
+                            dependency.0,
+                            package_meta.thunk_name.0
+                        );
+                    };
+
+                    eprintln!(
+                        "WARN: {} depends on banned package {}, using {} instead",
+                        package_meta.thunk_name.0, dependency.0, replacement.0
+                    );
+
+                    replacement.clone()
+                }
+                None => continue,
+            };
+
+            redirect_dependency(package_meta, index, replacement);
+        }
+    }
+
     Ok(())
 }
 
-pub fn resolve_package_dependencies(
-    package_path: &PathBuf,
-    packages_path: &PathBuf,
+/// Name shared by every version of a package, which banned versions are matched against. Taken
+/// from the thunk name where possible, since that's all a banned thunk that was never loaded has,
+/// and otherwise the package's true name, which every version shares.
+fn version_group_name(package_meta: &PackageMeta) -> &str {
+    match &package_meta.thunk_parts {
+        Some(parts) => &parts.name,
+        None => &package_meta.true_name,
+    }
+}
+
+fn redirect_dependency(package_meta: &mut PackageMeta, index: usize, replacement: PackageName) {
+    let dependency = std::mem::replace(&mut package_meta.dependencies[index], replacement.clone());
+
+    if let Some(name) = package_meta.dependency_thunk_names.remove(&dependency) {
+        package_meta
+            .dependency_thunk_names
+            .insert(replacement, name);
+    }
+}
+
+/// Resolves thunks while populating the registry, remembering failures and re-exported types.
+#[derive(Debug, Default)]
+struct ThunkResolver {
+    errors: Vec<ThunkError>,
+    /// Types re-exported by any thunk pointing at a package, keyed by thunk name. Has an entry for
+    /// every thunk resolved, including banned packages that are never loaded.
+    exported_types: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl ThunkResolver {
+    fn resolve(&mut self, thunk_path: &Path, packages_path: &Path) -> Option<(String, PathBuf)> {
+        match resolve_thunk(thunk_path, packages_path) {
+            Ok(thunk) => {
+                self.exported_types
+                    .entry(thunk.index_name.clone())
+                    .or_default()
+                    .extend(thunk.exported_types);
+
+                Some((thunk.index_name, thunk.index_path))
+            }
+            Err(err) => {
+                self.errors.push(err);
+                None
+            }
+        }
+    }
+}
+
+fn resolve_package_dependencies(
+    package_path: &Path,
+    packages_path: &Path,
+    thunks: &mut ThunkResolver,
 ) -> anyhow::Result<BTreeMap<PackageName, PathBuf>> {
     let mut dependencies = BTreeMap::new();
 
@@ -116,20 +335,12 @@ pub fn resolve_package_dependencies(
 
     for file in files {
         let path = file.path();
-        if let Ok((package_name, _)) = resolve_index_path(&path, packages_path).context(format!(
-            "Failed to resolve _Index path for package {path:?}"
-        )) {
-            if BANNED_PACKAGE_NAMES.contains(&&*package_name) {
-                dependencies.insert(
-                    PackageName(package_name),
-                    PathBuf::from_str("NO_PATH").unwrap(),
-                );
 
-                continue;
-            }
+        let Some((package_name, _)) = thunks.resolve(&path, packages_path) else {
+            continue;
+        };
 
-            dependencies.insert(PackageName(package_name), path);
-        }
+        dependencies.insert(PackageName(package_name), path);
     }
 
     Ok(dependencies)
@@ -139,43 +350,108 @@ pub fn resolve_package_dependencies(
 /// packages recursively
 fn populate_index_paths(
     index_paths: &mut BTreeMap<String, PathBuf>,
-    packages_path: &PathBuf,
+    packages_path: &Path,
     thunk_path: PathBuf,
+    config: &ExtractorConfig,
+    thunks: &mut ThunkResolver,
 ) -> anyhow::Result<()> {
-    if let Ok((package_name, index_path)) = resolve_index_path(&thunk_path, packages_path) {
-        let dependencies = resolve_package_dependencies(&index_path, packages_path)
-            .context("Failed to parse dependencies")?;
+    let Some((package_name, index_path)) = thunks.resolve(&thunk_path, packages_path) else {
+        return Ok(());
+    };
 
-        for (package_name, thunk_path) in dependencies {
-            if index_paths.contains_key(&package_name as &String) {
-                continue;
-            }
+    if index_paths.contains_key(&package_name) {
+        return Ok(());
+    }
+
+    index_paths.insert(package_name, index_path.clone());
+
+    let dependencies = resolve_package_dependencies(&index_path, packages_path, thunks)
+        .context("Failed to parse dependencies")?;
 
-            populate_index_paths(index_paths, packages_path, thunk_path)?;
+    for (package_name, thunk_path) in dependencies {
+        if index_paths.contains_key(&package_name as &String) || config.is_banned(&package_name) {
+            continue;
         }
 
-        index_paths.insert(package_name, index_path);
+        populate_index_paths(index_paths, packages_path, thunk_path, config, thunks)?;
     }
 
     Ok(())
 }
 
-fn parse_package_lock(package_path: &PathBuf) -> anyhow::Result<WallyLock> {
+fn parse_package_lock(
+    package_path: &Path,
+    package_name: &str,
+    config: &ExtractorConfig,
+) -> anyhow::Result<WallyLock> {
     let lock_path = package_path.join("lock.toml");
-    let lock_content = get_file_source(&lock_path).context("Failed to read lock.toml")?;
+    if !lock_path.exists() {
+        return infer_package_lock(package_path, package_name);
+    }
 
-    let lock_file =
+    let lock_content = get_file_source(&lock_path, config).context("Failed to read lock.toml")?;
+
+    let mut lock_file =
         toml::from_str::<WallyLock>(&lock_content).context("Failed to parse lock.toml")?;
+    lock_file.contents = Some(lock_content);
 
     Ok(lock_file)
 }
 
+/// Packages unpacked from a model file don't carry a `lock.toml`. Fall back to the name of the
+/// package's source folder and, when the thunk name ends in one, its version.
+fn infer_package_lock(package_path: &Path, package_name: &str) -> anyhow::Result<WallyLock> {
+    let thunk_parts = ThunkNameParts::parse(package_name);
+
+    let mut source_folders = fs::read_dir(package_path)?
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect::<Vec<String>>();
+    source_folders.sort();
+
+    // The source folder is named after the package, anything else is a nested dependency folder.
+    // `read_dir` order isn't defined, so fall back to the first folder by name.
+    let name = thunk_parts
+        .as_ref()
+        .and_then(|parts| source_folders.iter().find(|name| **name == parts.name))
+        .or_else(|| source_folders.first())
+        .cloned()
+        .context(format!("Package {package_name} has no source folder"))?;
+
+    let version = thunk_parts
+        .and_then(|parts| match parts.revision {
+            ThunkRevision::Version(version) => Some(version),
+            ThunkRevision::Commit(_) => None,
+        })
+        .unwrap_or_else(|| {
+            eprintln!(
+                "WARN: Package {package_name} has no lock.toml and no version in its name. \
+                Assuming 0.0.0, add a version override to publish it."
+            );
+
+            Version::new(0, 0, 0)
+        });
+
+    Ok(WallyLock {
+        name,
+        version,
+        contents: None,
+    })
+}
+
+#[derive(Debug, Default)]
+struct PackageSourceInfo {
+    lines_of_code: usize,
+    licenses: Vec<License>,
+    file_licenses: BTreeMap<PathBuf, FileLicense>,
+}
+
 fn get_package_source_info(
-    source_path: &PathBuf,
-) -> anyhow::Result<(usize, Vec<License>, Vec<PathBuf>)> {
-    let mut loc = 0;
-    let mut licenses = Vec::new();
-    let mut unlicensed_files = Vec::new();
+    source_path: &Path,
+    config: &ExtractorConfig,
+) -> anyhow::Result<PackageSourceInfo> {
+    let mut info = PackageSourceInfo::default();
 
     let dir = WalkDir::new(source_path).into_iter().filter_map(|e| e.ok());
     for file in dir {
@@ -187,37 +463,269 @@ fn get_package_source_info(
             continue;
         }
 
-        let source = get_file_source(path)?;
-        loc += source.lines().count();
+        let source = get_file_source(path, config)?;
+        info.lines_of_code += source.lines().count();
 
-        let license = infer_script_license(&source, &path.to_owned()).unwrap_or(License::NoLicense);
-        if !licenses.contains(&license) {
-            licenses.push(license.clone());
+        let file_license = detect_file_license(&source, path, config);
+        for license in &file_license.licenses {
+            if !info.licenses.contains(license) {
+                info.licenses.push(license.clone());
+            }
         }
 
-        if license == License::NoLicense {
-            unlicensed_files.push(path.to_owned());
+        info.file_licenses
+            .insert(path.strip_prefix(source_path)?.to_owned(), file_license);
+    }
+
+    Ok(info)
+}
+
+/// Reads the types exported from a package's entry point. A package whose entry point can't be
+/// parsed still gets extracted, just without its types.
+fn get_package_exported_types(source_path: &Path, config: &ExtractorConfig) -> Vec<ExportedType> {
+    let Some(entry_point) = find_entry_point(source_path) else {
+        return Vec::new();
+    };
+
+    let exported_types =
+        get_file_source(&entry_point, config).and_then(|source| collect_exported_types(&source));
+
+    match exported_types {
+        Ok(exported_types) => exported_types,
+        Err(err) => {
+            eprintln!("WARN: Failed to read exported types from {entry_point:?}: {err:#}");
+            Vec::new()
         }
     }
+}
 
-    Ok((loc, licenses, unlicensed_files))
+/// Name a dependency thunk is required by, e.g. `Shared` for `Shared.lua`.
+fn thunk_file_name(thunk_path: &Path) -> String {
+    thunk_path
+        .file_stem()
+        .expect("thunk has a file name")
+        .to_string_lossy()
+        .into_owned()
 }
 
-fn get_lua_files_in_path(path: &PathBuf) -> anyhow::Result<Vec<DirEntry>> {
+fn get_lua_files_in_path(path: &Path) -> anyhow::Result<Vec<DirEntry>> {
     let mut files = Vec::new();
-    for file in fs::read_dir(path)? {
-        if let Ok(file) = file {
-            let path = file.path();
-            let extension = path.extension();
-
-            // We only want to operate on Lua files
-            if extension.and_then(OsStr::to_str) != Some("lua") {
-                continue;
-            }
+    for file in fs::read_dir(path)?.flatten() {
+        let path = file.path();
+        let extension = path.extension();
 
-            files.push(file);
+        // We only want to operate on Lua files
+        if extension.and_then(OsStr::to_str) != Some("lua") {
+            continue;
         }
+
+        files.push(file);
     }
 
     Ok(files)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(
+        thunk_name: &str,
+        true_name: &str,
+        version: &str,
+        dependencies: &[(&str, &str)],
+    ) -> PackageMeta {
+        PackageMeta {
+            thunk_name: PackageName(thunk_name.to_owned()),
+            thunk_parts: ThunkNameParts::parse(thunk_name),
+            true_name: true_name.to_owned(),
+            version: Version::parse(version).unwrap(),
+            dependencies: dependencies
+                .iter()
+                .map(|(thunk_name, _)| PackageName(thunk_name.to_string()))
+                .collect(),
+            dependency_thunk_names: dependencies
+                .iter()
+                .map(|(thunk_name, alias)| (PackageName(thunk_name.to_string()), alias.to_string()))
+                .collect(),
+            ..PackageMeta::default()
+        }
+    }
+
+    fn config(banned_packages: &[&str]) -> ExtractorConfig {
+        ExtractorConfig {
+            banned_packages: banned_packages.iter().map(|i| i.to_string()).collect(),
+            dependency_aliases: BTreeMap::new(),
+            ..ExtractorConfig::default()
+        }
+    }
+
+    fn dependencies(package_meta: &PackageMeta) -> Vec<(&str, &str)> {
+        package_meta
+            .dependency_thunk_names
+            .iter()
+            .map(|(thunk_name, alias)| (thunk_name.0.as_str(), alias.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn banned_versions_are_redirected_to_the_newest_version() {
+        let mut package_metas = [
+            package(
+                "React-9c8468d8-8a7220fd",
+                "React",
+                "17.0.1",
+                &[("LuauPolyfill-2fca3173-0.4.2", "LuauPolyfill")],
+            ),
+            package("LuauPolyfill-2fca3173-1.0.0", "LuauPolyfill", "1.0.0", &[]),
+            package("LuauPolyfill-2fca3173-1.1.0", "LuauPolyfill", "1.1.0", &[]),
+        ];
+
+        redirect_banned_dependencies(
+            &mut package_metas,
+            &config(&["LuauPolyfill-2fca3173-0.4.2"]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            package_metas[0].dependencies,
+            vec![PackageName("LuauPolyfill-2fca3173-1.1.0".to_owned())]
+        );
+        assert_eq!(
+            dependencies(&package_metas[0]),
+            vec![("LuauPolyfill-2fca3173-1.1.0", "LuauPolyfill")]
+        );
+    }
+
+    #[test]
+    fn banned_versions_required_under_another_name_are_redirected() {
+        let mut package_metas = [
+            package(
+                "React-9c8468d8-8a7220fd",
+                "React",
+                "17.0.1",
+                &[("LuauPolyfill-2fca3173-0.4.2", "Polyfill")],
+            ),
+            package("LuauPolyfill-2fca3173-1.1.0", "LuauPolyfill", "1.1.0", &[]),
+        ];
+
+        redirect_banned_dependencies(
+            &mut package_metas,
+            &config(&["LuauPolyfill-2fca3173-0.4.2"]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            dependencies(&package_metas[0]),
+            vec![("LuauPolyfill-2fca3173-1.1.0", "Polyfill")]
+        );
+    }
+
+    #[test]
+    fn consumers_can_choose_a_version() {
+        let mut package_metas = [
+            package(
+                "React-9c8468d8-8a7220fd",
+                "React",
+                "17.0.1",
+                &[("LuauPolyfill-2fca3173-1.1.0", "LuauPolyfill")],
+            ),
+            package(
+                "Shared-9c8468d8-8a7220fd",
+                "Shared",
+                "17.0.1",
+                &[("LuauPolyfill-2fca3173-1.1.0", "LuauPolyfill")],
+            ),
+            package("LuauPolyfill-2fca3173-1.0.0", "LuauPolyfill", "1.0.0", &[]),
+            package("LuauPolyfill-2fca3173-1.1.0", "LuauPolyfill", "1.1.0", &[]),
+        ];
+
+        let mut config = config(&[]);
+        config.dependency_redirects.insert(
+            "React-9c8468d8-8a7220fd".to_owned(),
+            BTreeMap::from([(
+                "LuauPolyfill-2fca3173-1.1.0".to_owned(),
+                "LuauPolyfill-2fca3173-1.0.0".to_owned(),
+            )]),
+        );
+
+        redirect_banned_dependencies(&mut package_metas, &config).unwrap();
+
+        assert_eq!(
+            dependencies(&package_metas[0]),
+            vec![("LuauPolyfill-2fca3173-1.0.0", "LuauPolyfill")]
+        );
+        assert_eq!(
+            dependencies(&package_metas[1]),
+            vec![("LuauPolyfill-2fca3173-1.1.0", "LuauPolyfill")]
+        );
+    }
+
+    #[test]
+    fn redirects_to_packages_that_are_not_loaded_are_skipped() {
+        let mut package_metas = [package(
+            "React-9c8468d8-8a7220fd",
+            "React",
+            "17.0.1",
+            &[("LuauPolyfill-2fca3173-1.1.0", "LuauPolyfill")],
+        )];
+
+        let mut config = config(&[]);
+        config.dependency_redirects.insert(
+            "React-9c8468d8-8a7220fd".to_owned(),
+            BTreeMap::from([(
+                "LuauPolyfill-2fca3173-1.1.0".to_owned(),
+                "LuauPolyfill-2fca3173-9.9.9".to_owned(),
+            )]),
+        );
+
+        redirect_banned_dependencies(&mut package_metas, &config).unwrap();
+
+        assert_eq!(
+            dependencies(&package_metas[0]),
+            vec![("LuauPolyfill-2fca3173-1.1.0", "LuauPolyfill")]
+        );
+    }
+
+    #[test]
+    fn banned_versions_without_another_version_fail() {
+        let mut package_metas = [package(
+            "React-9c8468d8-8a7220fd",
+            "React",
+            "17.0.1",
+            &[("LuauPolyfill-2fca3173-0.4.2", "LuauPolyfill")],
+        )];
+
+        let error = redirect_banned_dependencies(
+            &mut package_metas,
+            &config(&["LuauPolyfill-2fca3173-0.4.2"]),
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(error.contains(
+            "React-9c8468d8-8a7220fd depends on banned package LuauPolyfill-2fca3173-0.4.2"
+        ));
+    }
+
+    #[test]
+    fn banned_consumers_keep_banned_dependencies() {
+        let mut package_metas = [package(
+            "React-04005deb-0fbbfa70",
+            "React",
+            "17.0.0",
+            &[("LuauPolyfill-2fca3173-0.4.2", "LuauPolyfill")],
+        )];
+
+        redirect_banned_dependencies(
+            &mut package_metas,
+            &config(&["React-04005deb-0fbbfa70", "LuauPolyfill-2fca3173-0.4.2"]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            dependencies(&package_metas[0]),
+            vec![("LuauPolyfill-2fca3173-0.4.2", "LuauPolyfill")]
+        );
+    }
+}
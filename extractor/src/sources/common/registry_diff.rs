@@ -0,0 +1,496 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use semver::Version;
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use walkdir::WalkDir;
+
+use crate::domain::{License, PackageMeta, PackageName};
+
+use super::PackageRegistry;
+
+/// Everything that changed between two snapshots of CorePackages.
+#[derive(Debug, Serialize)]
+pub struct RegistryDiff {
+    pub added: Vec<PackageName>,
+    pub removed: Vec<PackageName>,
+    /// Packages present in both snapshots with at least one change.
+    pub changed: Vec<PackageDiff>,
+}
+
+impl RegistryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Changes to a single package. Thunk names can differ when Roblox bumps the package hashes.
+#[derive(Debug, Serialize)]
+pub struct PackageDiff {
+    pub true_name: String,
+    pub old_thunk_name: PackageName,
+    pub new_thunk_name: PackageName,
+    /// Version from `lock.toml`, before version overrides. Both snapshots are loaded with the same
+    /// config, so an override would hide the upstream bump.
+    pub version: Option<Change<Version>>,
+    pub licenses: Option<Change<Vec<License>>>,
+    pub added_dependencies: Vec<String>,
+    pub removed_dependencies: Vec<String>,
+    pub files: Vec<FileDiff>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Change<T> {
+    pub old: T,
+    pub new: T,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileChange {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileDiff {
+    /// Path of the file relative to the package source folder.
+    pub path: PathBuf,
+    pub change: FileChange,
+    pub insertions: usize,
+    pub deletions: usize,
+    /// Unified diff of the file, `None` for binary files.
+    pub patch: Option<String>,
+}
+
+/// Compares two registries. Packages are paired up by thunk name first, then by true name for
+/// packages whose thunk name changed.
+pub fn diff_registries(
+    old_registry: &PackageRegistry,
+    new_registry: &PackageRegistry,
+) -> anyhow::Result<RegistryDiff> {
+    let mut pairs = Vec::new();
+    let mut removed = Vec::new();
+
+    let mut unmatched_new = new_registry
+        .packages()
+        .map(|package| &package.thunk_name)
+        .filter(|thunk_name| old_registry.get_package(thunk_name).is_none())
+        .collect::<BTreeSet<&PackageName>>();
+
+    for old_package in old_registry.packages() {
+        let thunk_name = &old_package.thunk_name;
+
+        if let Some(new_package) = new_registry.get_package(thunk_name) {
+            pairs.push((old_package, new_package));
+            continue;
+        }
+
+        match find_renamed_package(old_package, &unmatched_new, new_registry) {
+            Some(new_package) => {
+                unmatched_new.remove(&new_package.thunk_name);
+                pairs.push((old_package, new_package));
+            }
+            None => removed.push(thunk_name.to_owned()),
+        }
+    }
+
+    let mut changed = Vec::new();
+    for (old_package, new_package) in pairs {
+        let package_diff = diff_package(old_package, old_registry, new_package, new_registry)
+            .context(format!(
+                "Failed to diff package {:?}",
+                new_package.thunk_name
+            ))?;
+
+        if let Some(package_diff) = package_diff {
+            changed.push(package_diff);
+        }
+    }
+
+    Ok(RegistryDiff {
+        added: unmatched_new.into_iter().cloned().collect(),
+        removed,
+        changed,
+    })
+}
+
+/// Finds the package a renamed thunk became, as long as only one unmatched package shares its true
+/// name (several versions of a package can't be told apart).
+fn find_renamed_package<'a>(
+    old_package: &PackageMeta,
+    unmatched_new: &BTreeSet<&PackageName>,
+    new_registry: &'a PackageRegistry,
+) -> Option<&'a PackageMeta> {
+    let mut candidates = unmatched_new
+        .iter()
+        .filter_map(|thunk_name| new_registry.get_package(thunk_name))
+        .filter(|package| package.true_name == old_package.true_name);
+
+    match (candidates.next(), candidates.next()) {
+        (Some(package), None) => Some(package),
+        _ => None,
+    }
+}
+
+fn diff_package(
+    old_package: &PackageMeta,
+    old_registry: &PackageRegistry,
+    new_package: &PackageMeta,
+    new_registry: &PackageRegistry,
+) -> anyhow::Result<Option<PackageDiff>> {
+    let version = (old_package.lock_version != new_package.lock_version).then(|| Change {
+        old: old_package.lock_version.clone(),
+        new: new_package.lock_version.clone(),
+    });
+
+    let licenses = (old_package.licenses != new_package.licenses).then(|| Change {
+        old: old_package.licenses.clone(),
+        new: new_package.licenses.clone(),
+    });
+
+    let old_dependencies = describe_dependencies(old_package, old_registry);
+    let new_dependencies = describe_dependencies(new_package, new_registry);
+
+    let files = diff_package_files(&old_package.package_path, &new_package.package_path)?;
+
+    let package_diff = PackageDiff {
+        true_name: new_package.true_name.to_owned(),
+        old_thunk_name: old_package.thunk_name.to_owned(),
+        new_thunk_name: new_package.thunk_name.to_owned(),
+        version,
+        licenses,
+        added_dependencies: new_dependencies
+            .difference(&old_dependencies)
+            .cloned()
+            .collect(),
+        removed_dependencies: old_dependencies
+            .difference(&new_dependencies)
+            .cloned()
+            .collect(),
+        files,
+    };
+
+    let unchanged = package_diff.version.is_none()
+        && package_diff.licenses.is_none()
+        && package_diff.added_dependencies.is_empty()
+        && package_diff.removed_dependencies.is_empty()
+        && package_diff.files.is_empty();
+
+    Ok((!unchanged).then_some(package_diff))
+}
+
+/// Describes each dependency as `Alias = TrueName@version`, which stays the same when only the
+/// thunk hashes change.
+fn describe_dependencies(
+    package: &PackageMeta,
+    package_registry: &PackageRegistry,
+) -> BTreeSet<String> {
+    package
+        .dependency_thunk_names
+        .iter()
+        .map(
+            |(thunk_name, alias)| match package_registry.get_package(thunk_name) {
+                Some(dependency) => {
+                    format!("{alias} = {}@{}", dependency.true_name, dependency.version)
+                }
+                None => format!("{alias} = {}", thunk_name.0),
+            },
+        )
+        .collect()
+}
+
+fn diff_package_files(old_path: &Path, new_path: &Path) -> anyhow::Result<Vec<FileDiff>> {
+    let old_files = collect_files(old_path)?;
+    let new_files = collect_files(new_path)?;
+
+    let mut file_diffs = Vec::new();
+
+    for (relative_path, old_contents) in &old_files {
+        let new_contents = new_files.get(relative_path);
+
+        if new_contents == Some(old_contents) {
+            continue;
+        }
+
+        let (change, new_contents) = match new_contents {
+            Some(new_contents) => (FileChange::Modified, new_contents.as_slice()),
+            None => (FileChange::Removed, [].as_slice()),
+        };
+
+        file_diffs.push(diff_file(relative_path, change, old_contents, new_contents));
+    }
+
+    for (relative_path, new_contents) in &new_files {
+        if !old_files.contains_key(relative_path) {
+            file_diffs.push(diff_file(
+                relative_path,
+                FileChange::Added,
+                &[],
+                new_contents,
+            ));
+        }
+    }
+
+    file_diffs.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(file_diffs)
+}
+
+fn diff_file(relative_path: &Path, change: FileChange, old: &[u8], new: &[u8]) -> FileDiff {
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        return FileDiff {
+            path: relative_path.to_owned(),
+            change,
+            insertions: 0,
+            deletions: 0,
+            patch: None,
+        };
+    };
+
+    let text_diff = TextDiff::from_lines(old, new);
+
+    let mut insertions = 0;
+    let mut deletions = 0;
+    for change in text_diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => insertions += 1,
+            ChangeTag::Delete => deletions += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+
+    let display_path = relative_path.display().to_string();
+    let patch = text_diff
+        .unified_diff()
+        .header(&format!("a/{display_path}"), &format!("b/{display_path}"))
+        .to_string();
+
+    FileDiff {
+        path: relative_path.to_owned(),
+        change,
+        insertions,
+        deletions,
+        patch: Some(patch),
+    }
+}
+
+/// Reads every file under a package's source folder, keyed by its path relative to the folder.
+fn collect_files(root_path: &Path) -> anyhow::Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+
+    let entries = WalkDir::new(root_path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file());
+
+    for entry in entries {
+        let path = entry.path();
+        let contents = fs::read(path).context(format!("Failed to read path {path:?}"))?;
+
+        files.insert(path.strip_prefix(root_path)?.to_owned(), contents);
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::ExtractorConfig;
+
+    use super::*;
+
+    /// A package whose source folder holds `files`, under `root`.
+    fn package(
+        root: &Path,
+        thunk_name: &str,
+        true_name: &str,
+        version: &str,
+        files: &[(&str, &[u8])],
+    ) -> PackageMeta {
+        let package_path = root.join(thunk_name);
+        fs::create_dir_all(&package_path).unwrap();
+        for (file, contents) in files {
+            fs::write(package_path.join(file), contents).unwrap();
+        }
+
+        PackageMeta {
+            thunk_name: PackageName(thunk_name.to_owned()),
+            true_name: true_name.to_owned(),
+            lock_version: Version::parse(version).unwrap(),
+            package_path,
+            ..PackageMeta::default()
+        }
+    }
+
+    fn registry(packages: Vec<PackageMeta>) -> PackageRegistry {
+        let mut registry =
+            PackageRegistry::new(ExtractorConfig::default(), "test".into(), PathBuf::new());
+        for package in packages {
+            registry.add_package(package);
+        }
+
+        registry
+    }
+
+    fn thunk_names(names: &[PackageName]) -> Vec<&str> {
+        names.iter().map(|i| i.0.as_str()).collect()
+    }
+
+    #[test]
+    fn registries_are_diffed_by_package() {
+        let old_root = tempfile::tempdir().unwrap();
+        let new_root = tempfile::tempdir().unwrap();
+        let (old_root, new_root) = (old_root.path(), new_root.path());
+
+        let old_registry = registry(vec![
+            package(
+                old_root,
+                "Same-1",
+                "Same",
+                "1.0.0",
+                &[("init.lua", b"return 1\n")],
+            ),
+            package(old_root, "Gone-1", "Gone", "1.0.0", &[]),
+            PackageMeta {
+                licenses: vec![License::MIT],
+                ..package(
+                    old_root,
+                    "React-aaaaaaaa",
+                    "React",
+                    "17.0.1",
+                    &[
+                        ("init.lua", b"local a = 1\nreturn a\n"),
+                        ("Util.lua", b"return {}\n"),
+                        ("icon.png", &[0xff, 0x00]),
+                    ],
+                )
+            },
+        ]);
+
+        let new_registry = registry(vec![
+            package(
+                new_root,
+                "Same-1",
+                "Same",
+                "1.0.0",
+                &[("init.lua", b"return 1\n")],
+            ),
+            package(new_root, "Added-1", "Added", "1.0.0", &[]),
+            PackageMeta {
+                licenses: vec![License::MIT, License::Apache2],
+                dependency_thunk_names: BTreeMap::from([(
+                    PackageName("Same-1".to_owned()),
+                    "Same".to_owned(),
+                )]),
+                ..package(
+                    new_root,
+                    "React-bbbbbbbb",
+                    "React",
+                    "17.2.0",
+                    &[
+                        ("init.lua", b"local a = 2\nreturn a\n"),
+                        ("New.lua", b"return {}\n"),
+                        ("icon.png", &[0xff, 0x01]),
+                    ],
+                )
+            },
+        ]);
+
+        let diff = diff_registries(&old_registry, &new_registry).unwrap();
+
+        assert_eq!(thunk_names(&diff.added), ["Added-1"]);
+        assert_eq!(thunk_names(&diff.removed), ["Gone-1"]);
+        assert_eq!(diff.changed.len(), 1);
+
+        let react = &diff.changed[0];
+        assert_eq!(react.old_thunk_name.0, "React-aaaaaaaa");
+        assert_eq!(react.new_thunk_name.0, "React-bbbbbbbb");
+        assert_eq!(
+            react.version,
+            Some(Change {
+                old: Version::new(17, 0, 1),
+                new: Version::new(17, 2, 0),
+            })
+        );
+        assert_eq!(
+            react.licenses,
+            Some(Change {
+                old: vec![License::MIT],
+                new: vec![License::MIT, License::Apache2],
+            })
+        );
+        assert_eq!(react.added_dependencies, ["Same = Same@0.0.0"]);
+        assert!(react.removed_dependencies.is_empty());
+
+        let files = react
+            .files
+            .iter()
+            .map(|file| {
+                (
+                    file.path.to_str().unwrap(),
+                    file.change,
+                    file.insertions,
+                    file.deletions,
+                    file.patch.is_some(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                ("New.lua", FileChange::Added, 1, 0, true),
+                ("Util.lua", FileChange::Removed, 0, 1, true),
+                ("icon.png", FileChange::Modified, 0, 0, false),
+                ("init.lua", FileChange::Modified, 1, 1, true),
+            ]
+        );
+
+        let patch = react.files[3].patch.as_deref().unwrap();
+        assert!(patch.starts_with("--- a/init.lua\n+++ b/init.lua\n"));
+        assert!(patch.contains("-local a = 1\n+local a = 2\n"));
+    }
+
+    #[test]
+    fn identical_registries_have_no_diff() {
+        let root = tempfile::tempdir().unwrap();
+        let files: &[(&str, &[u8])] = &[("init.lua", b"return 1\n")];
+
+        let old_registry = registry(vec![package(root.path(), "Same-1", "Same", "1.0.0", files)]);
+        let new_registry = registry(vec![package(root.path(), "Same-1", "Same", "1.0.0", files)]);
+
+        assert!(diff_registries(&old_registry, &new_registry)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn renamed_thunks_with_several_versions_are_not_paired() {
+        let old_root = tempfile::tempdir().unwrap();
+        let new_root = tempfile::tempdir().unwrap();
+
+        let old_registry = registry(vec![package(
+            old_root.path(),
+            "Polyfill-1",
+            "Polyfill",
+            "1.0.0",
+            &[],
+        )]);
+        let new_registry = registry(vec![
+            package(new_root.path(), "Polyfill-2", "Polyfill", "1.0.0", &[]),
+            package(new_root.path(), "Polyfill-3", "Polyfill", "2.0.0", &[]),
+        ]);
+
+        let diff = diff_registries(&old_registry, &new_registry).unwrap();
+
+        assert_eq!(thunk_names(&diff.added), ["Polyfill-2", "Polyfill-3"]);
+        assert_eq!(thunk_names(&diff.removed), ["Polyfill-1"]);
+        assert!(diff.changed.is_empty());
+    }
+}
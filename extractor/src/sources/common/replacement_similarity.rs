@@ -0,0 +1,211 @@
+//! Compares each source replacement against the upstream file it replaces, token by token, to
+//! catch rewrites that are too close to the original to count as independent.
+//!
+//! Whitespace and comments are ignored, so reformatting a file or changing its header doesn't
+//! make it any less similar.
+
+use std::{ffi::OsStr, fs, path::PathBuf};
+
+use anyhow::Context;
+use full_moon::tokenizer::{Token, TokenType};
+use serde::Serialize;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+use walkdir::WalkDir;
+
+use crate::domain::PackageName;
+
+use super::source_utils::find_source_replacement;
+use super::PackageRegistry;
+
+#[derive(Debug, Serialize)]
+pub struct ReplacementSimilarity {
+    pub package: PackageName,
+    /// Path of the replaced file relative to the package's source folder.
+    pub file: PathBuf,
+    /// Key of the replacement in the config.
+    pub replacement: String,
+    /// Share of tokens the two files have in common, in order, from 0 to 1.
+    pub similarity: f32,
+    /// Longest run of tokens copied unchanged from the original.
+    pub longest_shared_run: usize,
+    pub original_tokens: usize,
+    pub replacement_tokens: usize,
+    /// Whether the similarity is over the threshold.
+    pub too_similar: bool,
+}
+
+/// Compares every replaced file in the registry against its replacement. Replacements that don't
+/// match any file are warned about, since they're most likely out of date.
+pub fn compare_replacements(
+    package_registry: &PackageRegistry,
+    threshold: f32,
+) -> anyhow::Result<Vec<ReplacementSimilarity>> {
+    let config = package_registry.config();
+
+    let mut comparisons = Vec::new();
+
+    for package_meta in package_registry.packages() {
+        let files = WalkDir::new(&package_meta.package_path)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().and_then(OsStr::to_str) == Some("lua"));
+
+        for file in files {
+            let path = file.path();
+
+            let Some((replacement, content)) = find_source_replacement(path, config) else {
+                continue;
+            };
+
+            let original =
+                fs::read_to_string(path).context(format!("Failed to read path {path:?}"))?;
+
+            let original_tokens = significant_tokens(&original)
+                .context(format!("Failed to tokenize original file {path:?}"))?;
+            let replacement_tokens = significant_tokens(content)
+                .context(format!("Failed to tokenize replacement {replacement:?}"))?;
+
+            let (similarity, longest_shared_run) =
+                token_similarity(&original_tokens, &replacement_tokens);
+
+            comparisons.push(ReplacementSimilarity {
+                package: package_meta.thunk_name.to_owned(),
+                file: path.strip_prefix(&package_meta.package_path)?.to_owned(),
+                replacement: replacement.to_owned(),
+                similarity,
+                longest_shared_run,
+                original_tokens: original_tokens.len(),
+                replacement_tokens: replacement_tokens.len(),
+                too_similar: is_too_similar(similarity, threshold),
+            });
+        }
+    }
+
+    for replacement in config.source_replacements.keys() {
+        if !comparisons
+            .iter()
+            .any(|comparison| &comparison.replacement == replacement)
+        {
+            eprintln!("WARN: Source replacement {replacement:?} doesn't match any file");
+        }
+    }
+
+    Ok(comparisons)
+}
+
+/// Text of every token in a source file, apart from whitespace and comments.
+fn significant_tokens(source: &str) -> anyhow::Result<Vec<String>> {
+    let tokens = full_moon::tokenizer::tokens(source).map_err(|err| anyhow::anyhow!("{err}"))?;
+
+    Ok(tokens
+        .iter()
+        .filter(|token| !is_trivia(token))
+        .map(|token| token.to_string())
+        .collect())
+}
+
+fn is_trivia(token: &Token) -> bool {
+    matches!(
+        token.token_type(),
+        TokenType::Whitespace { .. }
+            | TokenType::SingleLineComment { .. }
+            | TokenType::MultiLineComment { .. }
+            | TokenType::Shebang { .. }
+            | TokenType::Eof
+    )
+}
+
+/// A replacement exactly at the threshold is still allowed.
+fn is_too_similar(similarity: f32, threshold: f32) -> bool {
+    similarity > threshold
+}
+
+/// Share of tokens both files have in common, in the same order, along with the longest run of
+/// matching tokens. Myers' diff finds the longest common subsequence, where patience diff would
+/// only anchor on tokens that appear once and miss most of the `end`s and `local`s Lua is made of.
+fn token_similarity(original: &[String], replacement: &[String]) -> (f32, usize) {
+    let total = original.len() + replacement.len();
+    if total == 0 {
+        return (1.0, 0);
+    }
+
+    let ops = capture_diff_slices(Algorithm::Myers, original, replacement);
+
+    let mut shared = 0;
+    let mut run = 0;
+    let mut longest_shared_run = 0;
+
+    for op in ops {
+        match op {
+            DiffOp::Equal { len, .. } => {
+                shared += len;
+                run += len;
+                longest_shared_run = longest_shared_run.max(run);
+            }
+            _ => run = 0,
+        }
+    }
+
+    ((2 * shared) as f32 / total as f32, longest_shared_run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn similarity(original: &str, replacement: &str) -> (f32, usize) {
+        token_similarity(
+            &significant_tokens(original).unwrap(),
+            &significant_tokens(replacement).unwrap(),
+        )
+    }
+
+    #[test]
+    fn identical_files_are_fully_similar() {
+        let source = "local function add(a, b)\n\treturn a + b\nend\n\nreturn add\n";
+
+        assert_eq!(similarity(source, source), (1.0, 15));
+    }
+
+    #[test]
+    fn formatting_and_comments_are_ignored() {
+        assert_eq!(
+            similarity(
+                "local x = 1 return x",
+                "-- Rewritten\nlocal   x =\n\t1\n\nreturn x -- the value\n"
+            ),
+            (1.0, 6)
+        );
+    }
+
+    #[test]
+    fn disjoint_files_share_nothing() {
+        assert_eq!(
+            similarity("local x = 1", "while true do break end"),
+            (0.0, 0)
+        );
+    }
+
+    #[test]
+    fn repeated_tokens_are_matched() {
+        let original = "if a then return end\nif b then return end\nif c then return end\n";
+        let replacement = "if a then return end\nif d then return end\nif c then return end\n";
+
+        // Everything but `b`/`d` matches, even though no token appears only once on both sides
+        let (similarity, longest_shared_run) = similarity(original, replacement);
+        assert_eq!(similarity, 28.0 / 30.0);
+        assert_eq!(longest_shared_run, 8);
+    }
+
+    #[test]
+    fn threshold_is_exclusive() {
+        let original = ["a", "b", "c", "d"].map(String::from);
+        let replacement = ["a", "b", "x", "y"].map(String::from);
+
+        let (similarity, _) = token_similarity(&original, &replacement);
+        assert_eq!(similarity, 0.5);
+
+        assert!(!is_too_similar(similarity, 0.5));
+        assert!(is_too_similar(similarity, 0.49));
+    }
+}
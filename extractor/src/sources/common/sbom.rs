@@ -0,0 +1,729 @@
+//! Software bills of materials for an extraction, as SPDX 2.3 or CycloneDX 1.5 JSON documents
+//! written to the root of the output directory.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+
+use crate::domain::{spdx_expression, License, PackageMeta, PackageName};
+
+use super::{output::PackagePlan, source_utils::sha256_hex, PackageRegistry};
+
+const TOOL_NAME: &str = "extractor";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    /// SPDX 2.3 JSON, written to `sbom.spdx.json`.
+    Spdx,
+    /// CycloneDX 1.5 JSON, written to `sbom.cdx.json`.
+    CycloneDx,
+}
+
+impl SbomFormat {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            SbomFormat::Spdx => "sbom.spdx.json",
+            SbomFormat::CycloneDx => "sbom.cdx.json",
+        }
+    }
+}
+
+/// A package alias swapped in for a banned package, e.g. `evaera/promise@4.0.0`.
+struct ExternalPackage<'a> {
+    scope: &'a str,
+    name: &'a str,
+    version: &'a str,
+}
+
+impl<'a> ExternalPackage<'a> {
+    fn parse(reference: &'a str) -> Option<Self> {
+        let (scope, rest) = reference.split_once('/')?;
+        let (name, version) = rest.split_once('@')?;
+
+        Some(Self {
+            scope,
+            name,
+            version,
+        })
+    }
+}
+
+/// Everything a bill of materials needs about one extracted package.
+struct SbomPackage<'a> {
+    plan: &'a PackagePlan,
+    meta: &'a PackageMeta,
+    files: Vec<SbomFile<'a>>,
+}
+
+struct SbomFile<'a> {
+    /// Path relative to the output directory.
+    path: PathBuf,
+    sha1: String,
+    sha256: String,
+    /// Licenses of the original source file, `None` for generated files.
+    licenses: Option<&'a [License]>,
+    copyright_holders: &'a [String],
+}
+
+pub fn write_sboms(
+    formats: &[SbomFormat],
+    plans: &[PackagePlan],
+    package_registry: &PackageRegistry,
+    root_packages: &[PackageName],
+    output_path: &Path,
+) -> anyhow::Result<()> {
+    if formats.is_empty() {
+        return Ok(());
+    }
+
+    let packages = collect_sbom_packages(plans, package_registry)?;
+    let created = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+
+    // Identifies this particular extraction, as both formats want a unique document id
+    let document_hash = sha256_hex(
+        format!(
+            "{created}{}{}",
+            package_registry.source(),
+            root_packages
+                .iter()
+                .map(|thunk_name| thunk_name.0.as_str())
+                .collect::<Vec<&str>>()
+                .join(",")
+        )
+        .as_bytes(),
+    );
+
+    for format in formats {
+        let document = match format {
+            SbomFormat::Spdx => spdx_document(
+                &packages,
+                package_registry,
+                root_packages,
+                &created,
+                &document_hash,
+            ),
+            SbomFormat::CycloneDx => {
+                cyclonedx_document(&packages, package_registry, &created, &document_hash)
+            }
+        };
+
+        let path = output_path.join(format.file_name());
+        let mut contents = serde_json::to_string_pretty(&document)?;
+        contents.push('\n');
+
+        fs::write(&path, contents).context(format!("Failed to write to path {path:?}"))?;
+    }
+
+    Ok(())
+}
+
+fn collect_sbom_packages<'a>(
+    plans: &'a [PackagePlan],
+    package_registry: &'a PackageRegistry,
+) -> anyhow::Result<Vec<SbomPackage<'a>>> {
+    let mut packages = Vec::new();
+
+    for plan in plans {
+        let meta = package_registry
+            .get_package(&plan.thunk_name)
+            .context(format!(
+                "Package {:?} does not exist in registry",
+                plan.thunk_name
+            ))?;
+
+        let files = plan
+            .files
+            .iter()
+            .map(|(relative_path, file)| {
+                let file_license = file
+                    .original_path
+                    .as_deref()
+                    .and_then(|path| path.strip_prefix(&meta.package_path).ok())
+                    .and_then(|path| meta.file_licenses.get(path));
+
+                SbomFile {
+                    path: plan.folder.join(relative_path),
+                    sha1: format!("{:x}", Sha1::digest(file.contents.as_bytes())),
+                    sha256: sha256_hex(file.contents.as_bytes()),
+                    licenses: file_license.map(|i| i.licenses.as_slice()),
+                    copyright_holders: file_license
+                        .map(|i| i.copyright_holders.as_slice())
+                        .unwrap_or_default(),
+                }
+            })
+            .collect();
+
+        packages.push(SbomPackage { plan, meta, files });
+    }
+
+    Ok(packages)
+}
+
+/// Aliased packages depended on by any extracted package, keyed by their Wally reference.
+fn external_dependencies<'a>(
+    packages: &[SbomPackage<'a>],
+    package_registry: &PackageRegistry,
+) -> BTreeMap<&'a str, ExternalPackage<'a>> {
+    packages
+        .iter()
+        .flat_map(|package| &package.meta.dependency_thunk_names)
+        .filter(|(thunk_name, _)| package_registry.config().is_aliased(thunk_name))
+        .filter_map(|(_, reference)| {
+            ExternalPackage::parse(reference).map(|external| (reference.as_str(), external))
+        })
+        .collect()
+}
+
+/// Extracted packages and Wally references each package depends on.
+fn package_dependencies<'a>(
+    package: &SbomPackage<'a>,
+    packages: &[SbomPackage<'a>],
+    package_registry: &PackageRegistry,
+) -> Vec<DependencyRef<'a>> {
+    package
+        .meta
+        .dependency_thunk_names
+        .iter()
+        .filter_map(|(thunk_name, reference)| {
+            if package_registry.config().is_aliased(thunk_name) {
+                return Some(DependencyRef::External(reference.as_str()));
+            }
+
+            packages
+                .iter()
+                .position(|other| other.meta.thunk_name == *thunk_name)
+                .map(DependencyRef::Package)
+        })
+        .collect()
+}
+
+enum DependencyRef<'a> {
+    /// Index into the extracted packages.
+    Package(usize),
+    External(&'a str),
+}
+
+/// SPDX has no empty license expression, so packages and files without a license found in them
+/// are `NOASSERTION` instead.
+fn spdx_license(licenses: &[License]) -> String {
+    if licenses.is_empty() {
+        "NOASSERTION".into()
+    } else {
+        spdx_expression(licenses)
+    }
+}
+
+fn copyright_text(holders: impl IntoIterator<Item = impl AsRef<str>>) -> String {
+    let lines = holders
+        .into_iter()
+        .map(|holder| format!("Copyright (c) {}", holder.as_ref()))
+        .collect::<Vec<String>>();
+
+    if lines.is_empty() {
+        "NOASSERTION".into()
+    } else {
+        lines.join("\n")
+    }
+}
+
+/// SPDX identifiers may only contain letters, numbers, `.` and `-`. Names with anything else are
+/// suffixed with a short hash of the original name, so `a_b.lua` and `a-b.lua` (or `Foo-Bar/x.lua`
+/// and `Foo/Bar/x.lua`) still get different identifiers.
+fn spdx_ref(kind: &str, name: &str) -> String {
+    let sanitized = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+
+    if sanitized == name {
+        format!("SPDXRef-{kind}-{name}")
+    } else {
+        let hash = sha256_hex(name.as_bytes());
+        format!("SPDXRef-{kind}-{sanitized}-{}", &hash[..8])
+    }
+}
+
+fn spdx_document(
+    packages: &[SbomPackage],
+    package_registry: &PackageRegistry,
+    root_packages: &[PackageName],
+    created: &str,
+    document_hash: &str,
+) -> Value {
+    let package_ref =
+        |package: &SbomPackage| spdx_ref("Package", &package.plan.folder.to_string_lossy());
+
+    let mut spdx_packages = Vec::new();
+    let mut spdx_files = Vec::new();
+    let mut relationships = Vec::new();
+    let mut license_refs = Vec::new();
+
+    for package in packages {
+        let package_id = package_ref(package);
+        let license = spdx_license(&package.meta.licenses);
+
+        let file_licenses = package
+            .files
+            .iter()
+            .flat_map(|file| file.licenses.unwrap_or_default());
+        for license in package.meta.licenses.iter().chain(file_licenses) {
+            if license.spdx_id().starts_with("LicenseRef-") && !license_refs.contains(license) {
+                license_refs.push(license.clone());
+            }
+        }
+
+        if root_packages.contains(&package.meta.thunk_name) {
+            relationships.push(json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": package_id,
+            }));
+        }
+
+        // Sorted SHA-1s of every file, see the SPDX package verification code algorithm
+        let mut file_sha1s = package
+            .files
+            .iter()
+            .map(|file| file.sha1.as_str())
+            .collect::<Vec<&str>>();
+        file_sha1s.sort();
+
+        let mut file_ids = Vec::new();
+        for file in &package.files {
+            let file_id = spdx_ref("File", &file.path.to_string_lossy());
+
+            let license_info = match file.licenses {
+                Some(licenses) => licenses
+                    .iter()
+                    .map(|license| license.spdx_id().to_owned())
+                    .collect::<Vec<String>>(),
+                None => vec!["NOASSERTION".to_owned()],
+            };
+
+            spdx_files.push(json!({
+                "fileName": format!("./{}", file.path.to_string_lossy().replace('\\', "/")),
+                "SPDXID": file_id,
+                "checksums": [
+                    { "algorithm": "SHA1", "checksumValue": file.sha1 },
+                    { "algorithm": "SHA256", "checksumValue": file.sha256 },
+                ],
+                "licenseConcluded": file.licenses.map(spdx_license).unwrap_or_else(|| "NOASSERTION".into()),
+                "licenseInfoInFiles": license_info,
+                "copyrightText": copyright_text(file.copyright_holders),
+            }));
+
+            file_ids.push(file_id);
+        }
+
+        spdx_packages.push(json!({
+            "name": format!("core-packages/{}", package.meta.wally_complaint_name),
+            "SPDXID": package_id,
+            "versionInfo": package.meta.version.to_string(),
+            "supplier": "Organization: Roblox Corporation",
+            "downloadLocation": "NOASSERTION",
+            "sourceInfo": format!("Extracted from CorePackages thunk {} ({})", package.meta.thunk_name.0, package_registry.source()),
+            "filesAnalyzed": true,
+            "packageVerificationCode": {
+                "packageVerificationCodeValue": format!("{:x}", Sha1::digest(file_sha1s.concat().as_bytes())),
+            },
+            "licenseConcluded": license,
+            "licenseDeclared": license,
+            "licenseInfoFromFiles": package.meta.licenses.iter().map(License::spdx_id).collect::<Vec<&str>>(),
+            "copyrightText": copyright_text(package.meta.copyright_holders()),
+            "hasFiles": file_ids,
+        }));
+
+        for dependency in package_dependencies(package, packages, package_registry) {
+            let related = match dependency {
+                DependencyRef::Package(index) => package_ref(&packages[index]),
+                DependencyRef::External(reference) => spdx_ref("External", reference),
+            };
+
+            relationships.push(json!({
+                "spdxElementId": package_id,
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": related,
+            }));
+        }
+    }
+
+    for (reference, external) in external_dependencies(packages, package_registry) {
+        spdx_packages.push(json!({
+            "name": format!("{}/{}", external.scope, external.name),
+            "SPDXID": spdx_ref("External", reference),
+            "versionInfo": external.version,
+            "downloadLocation": "NOASSERTION",
+            "sourceInfo": format!("Wally package {reference}, swapped in for a banned CorePackage"),
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": "NOASSERTION",
+            "copyrightText": "NOASSERTION",
+        }));
+    }
+
+    let extracted_licenses = license_refs
+        .iter()
+        .map(|license| {
+            json!({
+                "licenseId": license.spdx_id(),
+                "name": license.to_string(),
+                "extractedText": format!("{license}, no license text is available."),
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": "core-packages",
+        "documentNamespace": format!("https://github.com/grilme99/CorePackages/sbom/{document_hash}"),
+        "creationInfo": {
+            "created": created,
+            "creators": [format!("Tool: {TOOL_NAME}-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": spdx_packages,
+        "files": spdx_files,
+        "hasExtractedLicensingInfos": extracted_licenses,
+        "relationships": relationships,
+    })
+}
+
+fn cyclonedx_document(
+    packages: &[SbomPackage],
+    package_registry: &PackageRegistry,
+    created: &str,
+    document_hash: &str,
+) -> Value {
+    let package_ref = |package: &SbomPackage| {
+        format!(
+            "core-packages/{}@{}",
+            package.meta.wally_complaint_name, package.meta.version
+        )
+    };
+
+    let mut components = Vec::new();
+    let mut dependencies = Vec::new();
+
+    for package in packages {
+        let files = package
+            .files
+            .iter()
+            .map(|file| {
+                let mut component = json!({
+                    "type": "file",
+                    "bom-ref": format!("{}#{}", package_ref(package), file.path.to_string_lossy().replace('\\', "/")),
+                    "name": file.path.to_string_lossy().replace('\\', "/"),
+                    "hashes": [
+                        { "alg": "SHA-1", "content": file.sha1 },
+                        { "alg": "SHA-256", "content": file.sha256 },
+                    ],
+                });
+
+                if let Some(licenses) = file.licenses.filter(|i| !i.is_empty()) {
+                    component["licenses"] = json!([{ "expression": spdx_expression(licenses) }]);
+                }
+
+                component
+            })
+            .collect::<Vec<Value>>();
+
+        let mut component = json!({
+            "type": "library",
+            "bom-ref": package_ref(package),
+            "group": "core-packages",
+            "name": package.meta.wally_complaint_name,
+            "version": package.meta.version.to_string(),
+            "supplier": { "name": "Roblox Corporation" },
+            "copyright": copyright_text(package.meta.copyright_holders()),
+            "description": format!("Extracted from CorePackages thunk {} ({})", package.meta.thunk_name.0, package_registry.source()),
+            "components": files,
+        });
+
+        // CycloneDX has no `NOASSERTION`, an unknown license is left out instead
+        if !package.meta.licenses.is_empty() {
+            component["licenses"] =
+                json!([{ "expression": spdx_expression(&package.meta.licenses) }]);
+        }
+
+        components.push(component);
+
+        let depends_on = package_dependencies(package, packages, package_registry)
+            .into_iter()
+            .map(|dependency| match dependency {
+                DependencyRef::Package(index) => package_ref(&packages[index]),
+                DependencyRef::External(reference) => reference.to_owned(),
+            })
+            .collect::<Vec<String>>();
+
+        dependencies.push(json!({
+            "ref": package_ref(package),
+            "dependsOn": depends_on,
+        }));
+    }
+
+    for (reference, external) in external_dependencies(packages, package_registry) {
+        components.push(json!({
+            "type": "library",
+            "bom-ref": reference,
+            "group": external.scope,
+            "name": external.name,
+            "version": external.version,
+            "description": "Wally package swapped in for a banned CorePackage",
+        }));
+    }
+
+    // Hash based, so it's a valid (if not random) version 4 UUID
+    let serial_number = format!(
+        "urn:uuid:{}-{}-4{}-8{}-{}",
+        &document_hash[0..8],
+        &document_hash[8..12],
+        &document_hash[13..16],
+        &document_hash[17..20],
+        &document_hash[20..32]
+    );
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": serial_number,
+        "version": 1,
+        "metadata": {
+            "timestamp": created,
+            "tools": [{ "name": TOOL_NAME, "version": env!("CARGO_PKG_VERSION") }],
+        },
+        "components": components,
+        "dependencies": dependencies,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::config::ExtractorConfig;
+    use crate::domain::{FileLicense, LicenseDetection};
+    use crate::sources::common::output::PlannedFile;
+    use crate::sources::common::source_utils::SourceKind;
+
+    /// A package in `folder` with an original source file for each of `files`.
+    fn package(
+        thunk_name: &str,
+        folder: &str,
+        files: &[(&str, &[License])],
+    ) -> (PackagePlan, PackageMeta) {
+        let package_path = PathBuf::from("Packages/_Index").join(thunk_name);
+
+        let plan = PackagePlan {
+            thunk_name: PackageName(thunk_name.to_owned()),
+            folder: PathBuf::from(folder),
+            files: files
+                .iter()
+                .map(|(path, _)| {
+                    let file = PlannedFile {
+                        kind: SourceKind::Original,
+                        original_path: Some(package_path.join(path)),
+                        contents: format!("-- {path}"),
+                    };
+
+                    (PathBuf::from(path), file)
+                })
+                .collect(),
+        };
+
+        let file_licenses = files
+            .iter()
+            .map(|(path, licenses)| {
+                let file_license = FileLicense {
+                    licenses: licenses.to_vec(),
+                    expression: None,
+                    detected_by: LicenseDetection::SpdxIdentifier,
+                    copyright_holders: vec!["Roblox Corporation".into()],
+                };
+
+                (PathBuf::from(path), file_license)
+            })
+            .collect::<BTreeMap<PathBuf, FileLicense>>();
+
+        let mut licenses = file_licenses
+            .values()
+            .flat_map(|i| i.licenses.clone())
+            .collect::<Vec<License>>();
+        licenses.sort();
+        licenses.dedup();
+
+        let meta = PackageMeta {
+            thunk_name: PackageName(thunk_name.to_owned()),
+            wally_complaint_name: folder.to_lowercase(),
+            licenses,
+            file_licenses,
+            package_path,
+            ..PackageMeta::default()
+        };
+
+        (plan, meta)
+    }
+
+    fn registry(metas: Vec<PackageMeta>) -> PackageRegistry {
+        let mut registry =
+            PackageRegistry::new(ExtractorConfig::default(), "test".into(), PathBuf::new());
+        for meta in metas {
+            registry.add_package(meta);
+        }
+
+        registry
+    }
+
+    fn spdx(packages: Vec<(PackagePlan, PackageMeta)>) -> Value {
+        let (plans, metas): (Vec<_>, Vec<_>) = packages.into_iter().unzip();
+        let roots = metas
+            .iter()
+            .map(|i| i.thunk_name.clone())
+            .collect::<Vec<_>>();
+        let registry = registry(metas);
+        let packages = collect_sbom_packages(&plans, &registry).unwrap();
+
+        spdx_document(&packages, &registry, &roots, "2024-01-01T00:00:00Z", "0")
+    }
+
+    fn cyclonedx(packages: Vec<(PackagePlan, PackageMeta)>) -> Value {
+        let (plans, metas): (Vec<_>, Vec<_>) = packages.into_iter().unzip();
+        let registry = registry(metas);
+        let packages = collect_sbom_packages(&plans, &registry).unwrap();
+
+        cyclonedx_document(
+            &packages,
+            &registry,
+            "2024-01-01T00:00:00Z",
+            &"0123456789abcdef".repeat(4),
+        )
+    }
+
+    fn find<'a>(items: &'a Value, key: &str, value: &str) -> &'a Value {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|i| i[key] == value)
+            .unwrap_or_else(|| panic!("No item with {key} {value}"))
+    }
+
+    #[test]
+    fn spdx_ids_are_unique() {
+        let document = spdx(vec![
+            package(
+                "Foo-Bar-1",
+                "Foo-Bar",
+                &[("x.lua", &[License::MIT]), ("a_b.lua", &[License::MIT])],
+            ),
+            package(
+                "Foo-Bar-2",
+                "Foo/Bar",
+                &[("x.lua", &[License::MIT]), ("a-b.lua", &[License::MIT])],
+            ),
+        ]);
+
+        let ids = document["packages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .chain(document["files"].as_array().unwrap())
+            .map(|i| i["SPDXID"].as_str().unwrap())
+            .collect::<Vec<&str>>();
+
+        assert_eq!(ids.len(), 6);
+        assert_eq!(ids.iter().collect::<BTreeSet<_>>().len(), ids.len());
+    }
+
+    #[test]
+    fn license_refs_are_extracted_licensing_infos() {
+        let document = spdx(vec![package(
+            "Foo-1",
+            "Foo",
+            &[
+                ("init.lua", &[License::MIT]),
+                ("Internal.lua", &[License::RobloxProprietary]),
+                ("Vendor.lua", &[License::Other("LicenseRef-Vendor".into())]),
+            ],
+        )]);
+
+        let license_ids = document["hasExtractedLicensingInfos"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["licenseId"].as_str().unwrap())
+            .collect::<Vec<&str>>();
+
+        assert_eq!(
+            license_ids,
+            ["LicenseRef-Vendor", "LicenseRef-Roblox-Proprietary"]
+        );
+        assert_eq!(
+            find(&document["files"], "fileName", "./Foo/Vendor.lua")["licenseConcluded"],
+            "LicenseRef-Vendor"
+        );
+    }
+
+    #[test]
+    fn unlicensed_spdx_packages_are_noassertion() {
+        let document = spdx(vec![
+            package("Empty-1", "Empty", &[]),
+            package("Foo-1", "Foo", &[("init.lua", &[])]),
+        ]);
+
+        let empty = find(&document["packages"], "name", "core-packages/empty");
+        assert_eq!(empty["licenseConcluded"], "NOASSERTION");
+        assert_eq!(empty["licenseDeclared"], "NOASSERTION");
+        assert_eq!(empty["hasFiles"], json!([]));
+
+        let file = find(&document["files"], "fileName", "./Foo/init.lua");
+        assert_eq!(file["licenseConcluded"], "NOASSERTION");
+    }
+
+    #[test]
+    fn cyclonedx_components_list_licenses_and_files() {
+        let document = cyclonedx(vec![
+            package(
+                "Foo-1",
+                "Foo",
+                &[("init.lua", &[License::MIT, License::Apache2])],
+            ),
+            package("Empty-1", "Empty", &[]),
+        ]);
+
+        assert_eq!(
+            document["serialNumber"],
+            "urn:uuid:01234567-89ab-4def-8123-456789abcdef"
+        );
+
+        let foo = find(&document["components"], "name", "foo");
+        assert_eq!(foo["bom-ref"], "core-packages/foo@0.0.0");
+        assert_eq!(
+            foo["licenses"],
+            json!([{ "expression": "MIT AND Apache-2.0" }])
+        );
+        assert_eq!(foo["copyright"], "Copyright (c) Roblox Corporation");
+
+        let file = find(&foo["components"], "name", "Foo/init.lua");
+        assert_eq!(file["bom-ref"], "core-packages/foo@0.0.0#Foo/init.lua");
+        assert_eq!(file["licenses"], foo["licenses"]);
+
+        let empty = find(&document["components"], "name", "empty");
+        assert!(empty.get("licenses").is_none());
+        assert_eq!(empty["copyright"], "NOASSERTION");
+    }
+}
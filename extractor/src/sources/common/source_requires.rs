@@ -0,0 +1,205 @@
+//! Finds which of its dependency thunks a package's source actually requires, e.g. `Shared` for
+//! `require(Packages.Shared)` where `local Packages = script.Parent.Parent`.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use full_moon::{
+    ast::{FunctionCall, LocalAssignment},
+    visitors::Visitor,
+};
+use walkdir::WalkDir;
+
+use crate::config::ExtractorConfig;
+
+use super::source_utils::get_file_source;
+use super::thunk_parser::{
+    expand_locals, expression_to_components, follow_require_path, match_require_call,
+};
+
+#[derive(Debug, Default)]
+pub struct PackageRequires {
+    /// Names required from the folder holding the package's dependency thunks.
+    pub required_names: BTreeSet<String>,
+    /// Source files that couldn't be parsed, so whatever they require is unknown.
+    pub unparsed_files: Vec<PathBuf>,
+    /// Requires whose path can't be followed, like one built from
+    /// `script:FindFirstAncestor("Packages")`, so whether they lead to a dependency is unknown.
+    pub unresolved_requires: BTreeSet<String>,
+}
+
+impl PackageRequires {
+    /// Whether every require in the package could be followed, so a dependency that isn't in
+    /// `required_names` really is never required.
+    pub fn is_complete(&self) -> bool {
+        self.unparsed_files.is_empty() && self.unresolved_requires.is_empty()
+    }
+}
+
+/// Scans every source file of the package at `source_path` for requires that lead into
+/// `index_path`, the folder containing the package and its dependency thunks.
+pub fn collect_package_requires(
+    source_path: &Path,
+    index_path: &Path,
+    config: &ExtractorConfig,
+) -> anyhow::Result<PackageRequires> {
+    let mut requires = PackageRequires::default();
+
+    let files = WalkDir::new(source_path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            matches!(
+                e.path().extension().and_then(OsStr::to_str),
+                Some("lua" | "luau")
+            )
+        });
+
+    for file in files {
+        let path = file.path();
+        let source = get_file_source(path, config)?;
+
+        let Ok(ast) = full_moon::parse(&source) else {
+            requires.unparsed_files.push(path.to_owned());
+            continue;
+        };
+
+        let mut visitor = RequireVisitor::default();
+        visitor.visit_ast(&ast);
+
+        requires
+            .unresolved_requires
+            .extend(visitor.unsupported_requires);
+
+        for components in visitor.requires {
+            let components = expand_locals(components, &mut visitor.locals.clone());
+
+            let Some(required_path) = follow_require_path(path, &components) else {
+                requires.unresolved_requires.insert(components.join("."));
+                continue;
+            };
+
+            // Requires within the package itself aren't dependencies
+            if required_path.starts_with(source_path) {
+                continue;
+            }
+
+            let Ok(relative_path) = required_path.strip_prefix(index_path) else {
+                continue;
+            };
+
+            if let Some(name) = relative_path.iter().next() {
+                requires
+                    .required_names
+                    .insert(name.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    Ok(requires)
+}
+
+/// Collects the path of every `require` in a module, along with locals those paths may start from.
+#[derive(Debug, Default)]
+struct RequireVisitor {
+    locals: HashMap<String, Vec<String>>,
+    requires: Vec<Vec<String>>,
+    /// Requires of something other than a path, like `require(getModule())`.
+    unsupported_requires: Vec<String>,
+}
+
+impl Visitor for RequireVisitor {
+    fn visit_local_assignment(&mut self, assignment: &LocalAssignment) {
+        for (name, expression) in assignment.names().iter().zip(assignment.expressions()) {
+            if let Ok(components) = expression_to_components(expression) {
+                self.locals.insert(name.token().to_string(), components);
+            }
+        }
+    }
+
+    fn visit_function_call(&mut self, call: &FunctionCall) {
+        match match_require_call(call) {
+            Ok(Some(components)) => self.requires.push(components),
+            Ok(None) => {}
+            Err(_) => self
+                .unsupported_requires
+                .push(call.to_string().trim().to_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn collects_requires_of_dependency_thunks() {
+        let index = tempfile::tempdir().unwrap();
+        let index_path = index.path();
+        let source_path = index_path.join("Scheduler");
+        fs::create_dir(&source_path).unwrap();
+
+        let files = [
+            // Required through a chain of locals
+            (
+                "init.lua",
+                "local Root = script.Parent\n\
+                 local Packages = Root\n\
+                 local Shared = require(Packages.Shared)\n\
+                 return require(script.Utils)\n",
+            ),
+            // Required directly, from a module below the package root
+            (
+                "Utils.lua",
+                "return require(script.Parent.Parent.LuauPolyfill)\n",
+            ),
+            // Dot and bracket indexes mix
+            (
+                "Forks.lua",
+                "return require(script.Parent.Parent[\"Promise\"])\n",
+            ),
+            // Paths the source's location can't be followed from
+            (
+                "Ancestor.lua",
+                "local Packages = script:FindFirstAncestor(\"Packages\")\n\
+                 return require(Packages.Cryo)\n",
+            ),
+            ("Dynamic.lua", "return require(getModule())\n"),
+            ("Broken.lua", "local = \n"),
+        ];
+
+        for (file, source) in files {
+            fs::write(source_path.join(file), source).unwrap();
+        }
+
+        let requires =
+            collect_package_requires(&source_path, index_path, &ExtractorConfig::default())
+                .unwrap();
+
+        assert_eq!(
+            requires.required_names,
+            BTreeSet::from([
+                "LuauPolyfill".to_owned(),
+                "Promise".to_owned(),
+                "Shared".to_owned()
+            ])
+        );
+        assert_eq!(
+            requires.unresolved_requires,
+            BTreeSet::from([
+                "Packages.Cryo".to_owned(),
+                "require(getModule())".to_owned()
+            ])
+        );
+        assert_eq!(
+            requires.unparsed_files,
+            vec![source_path.join("Broken.lua")]
+        );
+        assert!(!requires.is_complete());
+    }
+}
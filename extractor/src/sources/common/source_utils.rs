@@ -1,68 +1,99 @@
 use std::fs;
-use std::path::{Path, PathBuf};
-
-use crate::constants::{
-    ALLOWED_MODULES, APACHE_LICENSE_PHRASES, MIT_LICENSE_PHRASES, SOURCE_REPLACEMENTS,
-};
-use crate::domain::License;
-
-pub fn infer_script_license(source: &str, path: &PathBuf) -> Option<License> {
-    if source_matches_license_list(source, MIT_LICENSE_PHRASES.to_vec()) {
-        return Some(License::MIT);
-    } else {
-        if is_script_whitelisted(path) {
-            return Some(License::MIT);
-        }
-    }
+use std::path::Path;
 
-    if source_matches_license_list(source, APACHE_LICENSE_PHRASES.to_vec()) {
-        return Some(License::Apache2);
-    }
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 
-    None
+use crate::config::ExtractorConfig;
+
+/// How the contents of an extracted file were produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceKind {
+    /// Generated by the extractor, e.g. `wally.toml`.
+    Generated,
+    /// Copied as-is from the original package.
+    Original,
+    /// Swapped out for a rewrite from the source replacements.
+    Replaced,
+    /// An allowed module, copied with a header noting it's assumed to be MIT.
+    Allowed,
 }
 
 /// Returns a files source, supporting manual overrides for file rewrites
-pub fn get_file_source(path: &Path) -> anyhow::Result<String> {
-    for (replacement_path, content) in SOURCE_REPLACEMENTS.into_iter() {
-        let path = path.to_str().unwrap();
-        let path = path.replace("\\", "/");
+pub fn get_file_source(path: &Path, config: &ExtractorConfig) -> anyhow::Result<String> {
+    read_file_source(path, config).map(|(source, _)| source)
+}
 
-        if path.contains(replacement_path) {
-            return Ok((*content).to_owned());
-        }
+/// Like [`get_file_source`], but also reports whether the source was replaced or allowed.
+pub fn read_file_source(
+    path: &Path,
+    config: &ExtractorConfig,
+) -> anyhow::Result<(String, SourceKind)> {
+    if let Some((_, content)) = find_source_replacement(path, config) {
+        return Ok((content.to_owned(), SourceKind::Replaced));
     }
 
-    let mut source = fs::read_to_string(&path)?;
-    if is_script_whitelisted(&path.to_owned()) {
+    let mut source = fs::read_to_string(path)?;
+    if is_script_whitelisted(path, config) {
         source.insert_str(
             0,
             "-- NOTE: This file is too small and/or simple to be sufficiently rewritten under a new license. Assume MIT.\n",
         );
+
+        return Ok((source, SourceKind::Allowed));
     }
 
-    Ok(source)
+    Ok((source, SourceKind::Original))
 }
 
-fn source_matches_license_list(source: &str, license_list: Vec<&str>) -> bool {
-    for phrase in license_list {
-        if source.to_lowercase().contains(&phrase.to_lowercase()) {
-            return true;
-        }
-    }
+/// Hex encoded SHA-256 of some contents, used to record exactly what was extracted.
+pub fn sha256_hex(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
 
-    false
+/// The source replacement for a file, as its key in the config and the contents of the rewrite.
+pub(super) fn find_source_replacement<'a>(
+    path: &Path,
+    config: &'a ExtractorConfig,
+) -> Option<(&'a str, &'a str)> {
+    let path = path.to_string_lossy().replace('\\', "/");
+
+    config
+        .source_replacements
+        .iter()
+        .find(|(replacement_path, _)| path.contains(replacement_path.as_str()))
+        .map(|(replacement_path, content)| (replacement_path.as_str(), content.as_str()))
 }
 
-fn is_script_whitelisted(path: &PathBuf) -> bool {
-    let path = path.to_str().unwrap();
-    let path = path.replace("\\", "/");
+pub(super) fn is_script_whitelisted(path: &Path, config: &ExtractorConfig) -> bool {
+    let path = path.to_string_lossy().replace('\\', "/");
 
-    for module_path in ALLOWED_MODULES {
-        if path.contains(module_path) {
+    for module_path in &config.allowed_modules {
+        if path.contains(module_path.as_str()) {
             return true;
         }
     }
 
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths_are_matched_lossily() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let config = ExtractorConfig {
+            allowed_modules: vec!["Math/clz32.lua".to_owned()],
+            ..ExtractorConfig::default()
+        };
+
+        let path = Path::new(OsStr::from_bytes(b"Packages/\xff/Math/clz32.lua"));
+        assert!(is_script_whitelisted(path, &config));
+        assert!(find_source_replacement(path, &config).is_none());
+    }
+}
@@ -1,107 +1,454 @@
-use std::{fs, path::PathBuf};
+//! Resolves package thunks, the small modules Wally generates to point at a package in `_Index/`.
+//!
+//! Roblox ships a few shapes of thunk, all of which are understood here:
+//!
+//! ```lua
+//! local PackageIndex = script.Parent._Index
+//! local Package = require(PackageIndex["React-9c8468d8-8a7220fd"]["React"])
+//! export type Element = Package.Element
+//! return Package
+//! ```
+//!
+//! ```lua
+//! return require(script.Parent.Parent["React-9c8468d8-8a7220fd"]["React"])
+//! ```
+//!
+//! Require paths are followed relative to the thunk itself, so it doesn't matter how many
+//! `Parent`s lead to `_Index/` or whether it's reached through a local.
 
-use anyhow::{bail, Context};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use derive_more::Display;
 use full_moon::{
-    ast::{Call, Expression, FunctionArgs, Index, Stmt, Suffix, Value, Var},
+    ast::{
+        Call, Expression, FunctionArgs, FunctionCall, Index, LastStmt, Stmt, Suffix, Value, Var,
+    },
     tokenizer::TokenType,
 };
 
-pub fn resolve_index_path(
-    thunk_path: &PathBuf,
-    packages_path: &PathBuf,
-) -> anyhow::Result<(String, PathBuf)> {
-    let source = fs::read_to_string(thunk_path).context("Failed to read path to thunk")?;
+/// A package thunk that couldn't be resolved to a folder in `_Index/`.
+#[derive(Debug, Display)]
+#[display(fmt = "{:?}: {}", path, kind)]
+pub struct ThunkError {
+    pub path: PathBuf,
+    pub kind: ThunkErrorKind,
+}
+
+impl std::error::Error for ThunkError {}
+
+#[derive(Debug, Display)]
+pub enum ThunkErrorKind {
+    #[display(fmt = "failed to read thunk ({})", _0)]
+    Read(io::Error),
+    #[display(fmt = "failed to parse thunk ({})", _0)]
+    Parse(String),
+    #[display(fmt = "no require of the package index found")]
+    MissingRequire,
+    #[display(fmt = "unsupported expression `{}` ({})", expression, reason)]
+    UnsupportedExpression {
+        expression: String,
+        reason: &'static str,
+    },
+    #[display(fmt = "require path `{}` does not point into _Index", _0)]
+    OutsideIndex(String),
+    #[display(fmt = "resolved _Index path {:?} does not exist", _0)]
+    MissingIndexPath(PathBuf),
+}
+
+/// A package thunk resolved to the package folder it points at.
+#[derive(Debug)]
+pub struct ResolvedThunk {
+    /// Name of the package folder in `_Index/`, which is the package's thunk name.
+    pub index_name: String,
+    pub index_path: PathBuf,
+    /// Types re-exported by the thunk, e.g. `Element` for `export type Element = Package.Element`.
+    pub exported_types: Vec<String>,
+}
+
+pub fn resolve_thunk(thunk_path: &Path, packages_path: &Path) -> Result<ResolvedThunk, ThunkError> {
+    let error = |kind| ThunkError {
+        path: thunk_path.to_owned(),
+        kind,
+    };
 
-    let ast = full_moon::parse(&source).context("Failed to parse AST of thunk")?;
-    assert!(ast.nodes().last_stmt().is_some());
+    let source = fs::read_to_string(thunk_path).map_err(|err| error(ThunkErrorKind::Read(err)))?;
 
-    if let Some(Stmt::LocalAssignment(assignment)) = ast.nodes().stmts().nth(1) {
-        let require = assignment.expressions().iter().next().unwrap();
-        if let Ok(path_components) = match_require(require) {
-            let path_components = path_components.unwrap();
+    let ast =
+        full_moon::parse(&source).map_err(|err| error(ThunkErrorKind::Parse(err.to_string())))?;
 
-            let index_name: &str = path_components.iter().nth(1).unwrap();
+    // Locals the require path can be built from, e.g. `PackageIndex` -> `script.Parent._Index`
+    let mut locals = HashMap::new();
+    let mut require_components = None;
+    let mut exported_types = Vec::new();
 
-            let mut index_path = packages_path.clone();
-            index_path.push(format!("_Index/{index_name}"));
+    for stmt in ast.nodes().stmts() {
+        match stmt {
+            Stmt::LocalAssignment(assignment) => {
+                for (name, expression) in assignment.names().iter().zip(assignment.expressions()) {
+                    if let Some(components) = match_require(expression).map_err(error)? {
+                        require_components.get_or_insert(components);
+                    } else if let Ok(components) = expression_to_components(expression) {
+                        locals.insert(name.token().to_string(), components);
+                    }
+                }
+            }
+            Stmt::ExportedTypeDeclaration(declaration) => {
+                let type_name = declaration.type_declaration().type_name();
+                exported_types.push(type_name.token().to_string());
+            }
+            _ => {}
+        }
+    }
 
-            if !index_path.exists() {
-                bail!("Resolved _Index path does not exist")
+    if require_components.is_none() {
+        if let Some(LastStmt::Return(returns)) = ast.nodes().last_stmt() {
+            for expression in returns.returns() {
+                if let Some(components) = match_require(expression).map_err(error)? {
+                    require_components = Some(components);
+                    break;
+                }
             }
+        }
+    }
+
+    let Some(path_components) = require_components else {
+        return Err(error(ThunkErrorKind::MissingRequire));
+    };
+
+    let path_components = expand_locals(path_components, &mut locals);
+
+    let index_name = follow_require_path(thunk_path, &path_components)
+        .as_deref()
+        .and_then(|required_path| {
+            required_path
+                .strip_prefix(packages_path.join("_Index"))
+                .ok()
+        })
+        .and_then(|relative_path| relative_path.iter().next())
+        .map(|index_name| index_name.to_string_lossy().into_owned())
+        .ok_or_else(|| error(ThunkErrorKind::OutsideIndex(path_components.join("."))))?;
+
+    let mut index_path = packages_path.to_path_buf();
+    index_path.push(format!("_Index/{index_name}"));
+
+    if !index_path.exists() {
+        return Err(error(ThunkErrorKind::MissingIndexPath(index_path)));
+    }
+
+    Ok(ResolvedThunk {
+        index_name,
+        index_path,
+        exported_types,
+    })
+}
 
-            return Ok((index_name.to_owned(), index_path));
+/// Replaces a leading local with the path it was assigned, until the path starts at `script`.
+pub(super) fn expand_locals(
+    mut components: Vec<String>,
+    locals: &mut HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    // Each local is removed once used, so self-referencing locals can't loop forever
+    while let Some(local) = components.first().and_then(|prefix| locals.remove(prefix)) {
+        components.splice(0..1, local);
+    }
+
+    components
+}
+
+/// Maps a require path starting at `script` onto the filesystem, relative to the thunk.
+pub(super) fn follow_require_path(thunk_path: &Path, components: &[String]) -> Option<PathBuf> {
+    let (first, rest) = components.split_first()?;
+    if first != "script" {
+        return None;
+    }
+
+    // `script` is the thunk itself, unless it's the `init.lua` of a folder
+    let mut path = if thunk_path.file_stem()? == "init" {
+        thunk_path.parent()?.to_owned()
+    } else {
+        thunk_path.to_owned()
+    };
+
+    for component in rest {
+        if component == "Parent" {
+            path = path.parent()?.to_owned();
+        } else {
+            path.push(component);
         }
     }
 
-    bail!("Failed to parse require in package thunk");
+    Some(path)
+}
+
+fn unsupported(expression: &impl ToString, reason: &'static str) -> ThunkErrorKind {
+    ThunkErrorKind::UnsupportedExpression {
+        expression: expression.to_string().trim().to_owned(),
+        reason,
+    }
 }
 
 // https://github.com/JohnnyMorganz/wally-package-types/blob/master/src/command.rs#L50
-fn expression_to_components(expression: &Expression) -> Vec<String> {
+pub(super) fn expression_to_components(
+    expression: &Expression,
+) -> Result<Vec<String>, ThunkErrorKind> {
     let mut components = Vec::new();
 
-    match expression {
-        Expression::Value { value, .. } => match &**value {
-            Value::Var(Var::Expression(var_expression)) => {
-                components.push(var_expression.prefix().to_string().trim().to_string());
-
-                for suffix in var_expression.suffixes() {
-                    match suffix {
-                        Suffix::Index(index) => match index {
-                            Index::Dot { name, .. } => {
-                                components.push(name.to_string().trim().to_string());
-                            }
-                            Index::Brackets { expression, .. } => match expression {
-                                Expression::Value { value, .. } => match &**value {
-                                    Value::String(name) => match name.token_type() {
-                                        TokenType::StringLiteral { literal, .. } => {
-                                            components.push(literal.trim().to_string());
-                                        }
-                                        _ => panic!("non-string brackets index"),
-                                    },
-                                    _ => panic!("non-string brackets index"),
-                                },
-                                _ => panic!("non-string brackets index"),
-                            },
-                            _ => panic!("unknown index"),
+    let Expression::Value { value, .. } = expression else {
+        return Err(unsupported(expression, "unknown require expression"));
+    };
+
+    let var_expression = match &**value {
+        Value::Var(Var::Expression(var_expression)) => var_expression,
+        // A bare name, like a local assigned from another local
+        Value::Var(Var::Name(name)) => return Ok(vec![name.to_string().trim().to_string()]),
+        _ => return Err(unsupported(expression, "unknown require expression")),
+    };
+
+    components.push(var_expression.prefix().to_string().trim().to_string());
+
+    for suffix in var_expression.suffixes() {
+        let Suffix::Index(index) = suffix else {
+            return Err(unsupported(suffix, "incorrect suffix"));
+        };
+
+        match index {
+            Index::Dot { name, .. } => {
+                components.push(name.to_string().trim().to_string());
+            }
+            Index::Brackets { expression, .. } => {
+                let literal = match expression {
+                    Expression::Value { value, .. } => match &**value {
+                        Value::String(name) => match name.token_type() {
+                            TokenType::StringLiteral { literal, .. } => Some(literal),
+                            _ => None,
                         },
-                        _ => panic!("incorrect suffix"),
-                    }
-                }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+
+                let Some(literal) = literal else {
+                    return Err(unsupported(index, "non-string brackets index"));
+                };
+
+                components.push(literal.trim().to_string());
             }
-            _ => panic!("unknown require expression"),
-        },
-        _ => panic!("unknown require expression"),
-    };
+            _ => return Err(unsupported(index, "unknown index")),
+        }
+    }
 
-    components
+    Ok(components)
 }
 
 // https://github.com/JohnnyMorganz/wally-package-types/blob/master/src/command.rs#L90
-fn match_require(expression: &Expression) -> anyhow::Result<Option<Vec<String>>> {
-    match expression {
-        Expression::Value { value, .. } => match &**value {
-            Value::FunctionCall(call) => {
-                if call.prefix().to_string().trim() == "require" && call.suffixes().count() == 1 {
-                    if let Suffix::Call(Call::AnonymousCall(FunctionArgs::Parentheses {
-                        arguments,
-                        ..
-                    })) = call.suffixes().next().unwrap()
-                    {
-                        if arguments.len() == 1 {
-                            return Ok(Some(expression_to_components(
-                                arguments.iter().next().unwrap(),
-                            )));
-                        }
-                    }
-                } else {
-                    bail!("unknown require expression 3");
+/// Returns the path components of a `require(...)` call, or `None` if the expression isn't one.
+fn match_require(expression: &Expression) -> Result<Option<Vec<String>>, ThunkErrorKind> {
+    let Expression::Value { value, .. } = expression else {
+        return Ok(None);
+    };
+
+    let Value::FunctionCall(call) = &**value else {
+        return Ok(None);
+    };
+
+    match_require_call(call)
+}
+
+/// Returns the path components of a `require(...)` call, or `None` if the call isn't one.
+pub(super) fn match_require_call(
+    call: &FunctionCall,
+) -> Result<Option<Vec<String>>, ThunkErrorKind> {
+    // Anything after the call itself, like `require(...).field`, doesn't change what's required
+    if call.prefix().to_string().trim() != "require" {
+        return Ok(None);
+    }
+
+    if let Some(Suffix::Call(Call::AnonymousCall(FunctionArgs::Parentheses {
+        arguments, ..
+    }))) = call.suffixes().next()
+    {
+        if let (1, Some(argument)) = (arguments.len(), arguments.iter().next()) {
+            return expression_to_components(argument).map(Some);
+        }
+    }
+
+    Err(unsupported(call, "require must take a single argument"))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// A `Packages` folder with React in its `_Index`.
+    fn packages() -> TempDir {
+        let packages = tempfile::tempdir().unwrap();
+        fs::create_dir_all(packages.path().join("_Index/React-9c8468d8-8a7220fd/React")).unwrap();
+
+        packages
+    }
+
+    fn resolve(
+        packages: &TempDir,
+        thunk_path: &str,
+        source: &str,
+    ) -> Result<ResolvedThunk, ThunkError> {
+        let thunk_path = packages.path().join(thunk_path);
+        fs::write(&thunk_path, source).unwrap();
+
+        resolve_thunk(&thunk_path, packages.path())
+    }
+
+    fn error_kind(source: &str) -> ThunkErrorKind {
+        let packages = packages();
+        resolve(&packages, "React.lua", source).unwrap_err().kind
+    }
+
+    #[test]
+    fn non_string_bracket_indexes_are_unsupported() {
+        let kind = error_kind("return require(script.Parent._Index[1][\"React\"])\n");
+
+        assert!(
+            matches!(
+                kind,
+                ThunkErrorKind::UnsupportedExpression {
+                    reason: "non-string brackets index",
+                    ..
                 }
-            }
-            _ => bail!("unknown require expression 2"),
-        },
-        _ => bail!("unknown require expression 1"),
+            ),
+            "{kind}"
+        );
     }
 
-    Ok(None)
+    #[test]
+    fn requires_with_several_arguments_are_unsupported() {
+        let kind = error_kind("return require(script.Parent._Index, \"React\")\n");
+
+        assert!(
+            matches!(
+                kind,
+                ThunkErrorKind::UnsupportedExpression {
+                    reason: "require must take a single argument",
+                    ..
+                }
+            ),
+            "{kind}"
+        );
+    }
+
+    #[test]
+    fn thunks_without_a_require_are_missing_it() {
+        let kind = error_kind("local React = {}\nreturn React\n");
+
+        assert!(matches!(kind, ThunkErrorKind::MissingRequire), "{kind}");
+    }
+
+    #[test]
+    fn requires_outside_the_index_are_rejected() {
+        let kind = error_kind("return require(script.Parent.Vendor[\"React\"])\n");
+
+        assert!(
+            matches!(&kind, ThunkErrorKind::OutsideIndex(path) if path == "script.Parent.Vendor.React"),
+            "{kind}"
+        );
+    }
+
+    #[test]
+    fn requires_of_missing_index_folders_are_rejected() {
+        let kind = error_kind(
+            "return require(script.Parent._Index[\"React-00000000-00000000\"][\"React\"])\n",
+        );
+
+        assert!(
+            matches!(kind, ThunkErrorKind::MissingIndexPath(_)),
+            "{kind}"
+        );
+    }
+
+    #[test]
+    fn unparsable_and_unreadable_thunks_are_rejected() {
+        let kind = error_kind("return require(\n");
+        assert!(matches!(kind, ThunkErrorKind::Parse(_)), "{kind}");
+
+        let packages = packages();
+        let kind = resolve_thunk(&packages.path().join("Missing.lua"), packages.path())
+            .unwrap_err()
+            .kind;
+        assert!(matches!(kind, ThunkErrorKind::Read(_)), "{kind}");
+    }
+
+    fn assert_resolves_to_react(thunk: &ResolvedThunk, packages: &TempDir) {
+        assert_eq!(thunk.index_name, "React-9c8468d8-8a7220fd");
+        assert_eq!(
+            thunk.index_path,
+            packages.path().join("_Index/React-9c8468d8-8a7220fd")
+        );
+    }
+
+    #[test]
+    fn resolves_returned_requires() {
+        let packages = packages();
+        let thunk = resolve(
+            &packages,
+            "React.lua",
+            "return require(script.Parent._Index[\"React-9c8468d8-8a7220fd\"][\"React\"])\n",
+        )
+        .unwrap();
+
+        assert_resolves_to_react(&thunk, &packages);
+        assert!(thunk.exported_types.is_empty());
+    }
+
+    #[test]
+    fn resolves_dependency_thunks_next_to_a_package() {
+        let packages = packages();
+        fs::create_dir_all(packages.path().join("_Index/ReactRoblox-9c8468d8-8a7220fd")).unwrap();
+
+        let thunk = resolve(
+            &packages,
+            "_Index/ReactRoblox-9c8468d8-8a7220fd/React.lua",
+            "return require(script.Parent.Parent[\"React-9c8468d8-8a7220fd\"][\"React\"])\n",
+        )
+        .unwrap();
+
+        assert_resolves_to_react(&thunk, &packages);
+    }
+
+    #[test]
+    fn resolves_requires_through_an_index_local() {
+        let packages = packages();
+        let thunk = resolve(
+            &packages,
+            "React.lua",
+            "local PackageIndex = script.Parent._Index\n\
+             local Package = require(PackageIndex[\"React-9c8468d8-8a7220fd\"][\"React\"])\n\
+             return Package\n",
+        )
+        .unwrap();
+
+        assert_resolves_to_react(&thunk, &packages);
+    }
+
+    #[test]
+    fn collects_re_exported_types() {
+        let packages = packages();
+        let thunk = resolve(
+            &packages,
+            "React.lua",
+            "local PackageIndex = script.Parent._Index\n\
+             local Package = require(PackageIndex[\"React-9c8468d8-8a7220fd\"][\"React\"])\n\
+             export type Element = Package.Element\n\
+             export type Ref<T> = Package.Ref<T>\n\
+             return Package\n",
+        )
+        .unwrap();
+
+        assert_resolves_to_react(&thunk, &packages);
+        assert_eq!(thunk.exported_types, vec!["Element", "Ref"]);
+    }
 }
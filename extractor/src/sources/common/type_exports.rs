@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use full_moon::ast::Stmt;
+
+use crate::domain::ExportedType;
+
+/// Entry points a package's exported types are read from, in order of preference.
+const ENTRY_POINTS: [&str; 2] = ["init.lua", "init.luau"];
+
+pub fn find_entry_point(source_path: &Path) -> Option<PathBuf> {
+    ENTRY_POINTS
+        .iter()
+        .map(|entry_point| source_path.join(entry_point))
+        .find(|path| path.is_file())
+}
+
+/// Collects every top-level `export type` declaration in a module.
+pub fn collect_exported_types(source: &str) -> anyhow::Result<Vec<ExportedType>> {
+    let ast = full_moon::parse(source).context("Failed to parse AST of module")?;
+
+    let mut exported_types = Vec::new();
+
+    for stmt in ast.nodes().stmts() {
+        let Stmt::ExportedTypeDeclaration(declaration) = stmt else {
+            continue;
+        };
+
+        let type_declaration = declaration.type_declaration();
+
+        let mut generics = Vec::new();
+        let mut generic_names = Vec::new();
+
+        if let Some(generic_declaration) = type_declaration.generics() {
+            for generic in generic_declaration.generics() {
+                generics.push(generic.to_string().trim().to_owned());
+                generic_names.push(generic.parameter().to_string().trim().to_owned());
+            }
+        }
+
+        exported_types.push(ExportedType {
+            name: type_declaration.type_name().token().to_string(),
+            generics,
+            generic_names,
+        });
+    }
+
+    Ok(exported_types)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_top_level_exported_types() {
+        let exported_types = collect_exported_types(
+            r#"
+            local Types = require(script.Types)
+
+            export type Foo<T = any> = { value: T }
+            export type Bar<K, V = string> = { [K]: V }
+            export type Baz = Types.Baz
+            type Private = number
+
+            local function f()
+                return nil
+            end
+
+            return { f = f }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            exported_types,
+            [
+                ExportedType {
+                    name: "Foo".into(),
+                    generics: vec!["T = any".into()],
+                    generic_names: vec!["T".into()],
+                },
+                ExportedType {
+                    name: "Bar".into(),
+                    generics: vec!["K".into(), "V = string".into()],
+                    generic_names: vec!["K".into(), "V".into()],
+                },
+                ExportedType {
+                    name: "Baz".into(),
+                    generics: Vec::new(),
+                    generic_names: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn exported_types_are_re_exported_without_defaults() {
+        let exported_types =
+            collect_exported_types("export type Foo<T = any> = { value: T }\nreturn {}").unwrap();
+
+        assert_eq!(
+            exported_types[0].re_export("Module"),
+            "export type Foo<T = any> = Module.Foo<T>"
+        );
+    }
+
+    #[test]
+    fn unparsable_modules_are_an_error() {
+        assert!(collect_exported_types("export type = ").is_err());
+    }
+}
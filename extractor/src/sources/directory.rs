@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+
+use super::CorePackageSource;
+
+/// Extracts CorePackages from any directory laid out like Studio's `Packages/` folder, i.e. one
+/// containing package thunks and an `_Index/` directory.
+#[derive(Debug)]
+pub struct DirectoryPackageSource {
+    packages_path: PathBuf,
+}
+
+impl DirectoryPackageSource {
+    pub fn new(packages_path: PathBuf) -> Self {
+        Self { packages_path }
+    }
+}
+
+impl CorePackageSource for DirectoryPackageSource {
+    fn packages_path(&self) -> anyhow::Result<PathBuf> {
+        let packages_path = &self.packages_path;
+
+        if !packages_path.is_dir() {
+            bail!("Packages directory {packages_path:?} does not exist");
+        }
+
+        if !packages_path.join("_Index").is_dir() {
+            bail!("Packages directory {packages_path:?} does not contain an _Index directory");
+        }
+
+        Ok(packages_path.to_owned())
+    }
+
+    fn describe(&self) -> String {
+        format!("Packages directory {:?}", self.packages_path)
+    }
+}
@@ -1,26 +1,97 @@
+mod client_tracker;
 mod common;
+mod directory;
 mod local;
+mod model;
 
 use std::path::PathBuf;
 
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 
+pub use client_tracker::ClientTrackerPackageSource;
+pub use directory::DirectoryPackageSource;
 pub use local::LocalPackageSource;
+pub use model::ModelPackageSource;
 
-use crate::domain::PackageName;
+pub use common::attribution::{
+    build_attribution_report, AttributionReport, LicenseSummary, PackageAttribution,
+};
+pub use common::extraction::{
+    check_root_licenses, ensure_licensed, extract_packages, plan_extraction, update_packages,
+    verify_extraction, LicenseReport, PackageLicenseCheck, Verification,
+};
+pub use common::extraction_lock::{DriftKind, FileDrift, EXTRACTION_LOCK_FILE_NAME};
+pub use common::license_policy::{AppliedWaiver, LicenseViolation, ViolationKind};
+pub use common::output::{PackagePlan, PackageSync, PlannedFile, WrittenPackage};
+pub use common::registry_diff::{
+    diff_registries, Change, FileChange, FileDiff, PackageDiff, RegistryDiff,
+};
+pub use common::replacement_similarity::{compare_replacements, ReplacementSimilarity};
+pub use common::sbom::SbomFormat;
+pub use common::source_utils::SourceKind;
+pub use common::thunk_parser::{ThunkError, ThunkErrorKind};
+pub use common::PackageRegistry;
+
+use crate::config::ExtractorConfig;
+
+use common::package_resolution::populate_package_registry;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub enum Source {
     /// Fetch CorePackages from the local studio installation.
     Local,
-    // /// Fetch CorePackages from the online Client Tracker.
-    // ClientTracker,
+    /// Fetch CorePackages from an arbitrary directory laid out like `Packages/`.
+    Directory,
+    /// Fetch CorePackages from the `Packages` folder inside a binary model or place file.
+    Model,
+    /// Fetch CorePackages from a local mirror of the Client Tracker.
+    ClientTracker,
 }
 
 pub trait CorePackageSource {
-    fn extract_packages(
-        extract_to: &PathBuf,
-        root_packages: &Vec<PackageName>,
-        bypass_license_check: &bool,
-    ) -> anyhow::Result<()>;
+    /// Resolves the `Packages` directory (the one containing `_Index/`) for this source.
+    fn packages_path(&self) -> anyhow::Result<PathBuf>;
+
+    /// Describes where packages come from, recorded in the provenance of extracted packages.
+    fn describe(&self) -> String;
+
+    /// Collects every package in this source into a registry, along with meta information like
+    /// their line count and license information.
+    fn load_registry(&self, config: ExtractorConfig) -> anyhow::Result<PackageRegistry> {
+        let packages_path = self
+            .packages_path()
+            .context("Failed to find path to Packages")?;
+
+        eprintln!("Found path to Packages at: {packages_path:?}");
+
+        let mut package_registry =
+            PackageRegistry::new(config, self.describe(), packages_path.to_owned());
+        populate_package_registry(&mut package_registry, &packages_path)
+            .context("Failed to collect CorePackages")?;
+
+        let thunk_errors = package_registry.thunk_errors().collect::<Vec<_>>();
+        if !thunk_errors.is_empty() {
+            eprintln!(
+                "WARN: Skipped {} thunks that could not be parsed:",
+                thunk_errors.len()
+            );
+
+            for thunk_error in thunk_errors {
+                eprintln!("  {thunk_error}");
+            }
+        }
+
+        for cycle in package_registry.dependency_cycles() {
+            let cycle = cycle
+                .iter()
+                .map(|package_name| package_name.as_str())
+                .collect::<Vec<&str>>()
+                .join(" -> ");
+
+            eprintln!("WARN: Found dependency cycle {cycle}");
+        }
+
+        Ok(package_registry)
+    }
 }
@@ -0,0 +1,267 @@
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context};
+use rbx_dom_weak::{types::Variant, Instance, WeakDom};
+use tempfile::TempDir;
+
+use super::CorePackageSource;
+
+/// Extracts CorePackages from a Roblox model or place file, binary (`.rbxm`/`.rbxl`) or XML
+/// (`.rbxmx`/`.rbxlx`).
+///
+/// The `Packages` folder found in the DOM is unpacked into a temporary directory that mirrors
+/// the filesystem layout, so the same thunk parsing and registry population can run over it.
+#[derive(Debug)]
+pub struct ModelPackageSource {
+    model_path: PathBuf,
+    unpacked: TempDir,
+}
+
+impl ModelPackageSource {
+    pub fn open(model_path: &Path) -> anyhow::Result<Self> {
+        let extension = model_path.extension().and_then(OsStr::to_str);
+
+        let file = BufReader::new(
+            File::open(model_path).context(format!("Failed to open {model_path:?}"))?,
+        );
+
+        let dom = match extension {
+            Some("rbxm") | Some("rbxl") => {
+                rbx_binary::from_reader(file).context(format!("Failed to decode {model_path:?}"))?
+            }
+            Some("rbxmx") | Some("rbxlx") => rbx_xml::from_reader_default(file)
+                .context(format!("Failed to decode {model_path:?}"))?,
+            _ => bail!("{model_path:?} is not a Roblox model or place file"),
+        };
+
+        let packages = find_packages_folder(&dom).context(format!(
+            "Could not find a Packages folder with an _Index in {model_path:?}"
+        ))?;
+
+        let unpacked = TempDir::new().context("Failed to create temporary directory")?;
+        unpack_instance(&dom, packages, &unpacked.path().join("Packages"))
+            .context("Failed to unpack Packages folder")?;
+
+        Ok(Self {
+            model_path: model_path.to_owned(),
+            unpacked,
+        })
+    }
+}
+
+impl CorePackageSource for ModelPackageSource {
+    fn packages_path(&self) -> anyhow::Result<PathBuf> {
+        Ok(self.unpacked.path().join("Packages"))
+    }
+
+    fn describe(&self) -> String {
+        format!("model file {:?}", self.model_path)
+    }
+}
+
+/// Breadth-first search for the shallowest instance that has an `_Index` child.
+fn find_packages_folder(dom: &WeakDom) -> Option<&Instance> {
+    let mut queue = VecDeque::from([dom.root()]);
+
+    while let Some(instance) = queue.pop_front() {
+        let children = instance
+            .children()
+            .iter()
+            .filter_map(|referent| dom.get_by_ref(*referent));
+
+        for child in children {
+            if child.name == "_Index" {
+                return Some(instance);
+            }
+
+            queue.push_back(child);
+        }
+    }
+
+    None
+}
+
+/// Writes an instance and its descendants to disk the same way Rojo would read them back:
+/// scripts with children become a directory with an `init.lua`, everything else becomes a
+/// directory or a single `.lua` file.
+fn unpack_instance(dom: &WeakDom, instance: &Instance, path: &Path) -> anyhow::Result<()> {
+    let source = script_source(instance);
+
+    if instance.children().is_empty() {
+        if let Some(source) = source {
+            // Script names can contain dots (`Foo.roblox`), so append rather than replace.
+            let mut file_path = path.as_os_str().to_owned();
+            file_path.push(".lua");
+
+            fs::write(&file_path, source).context(format!("Failed to write script {path:?}"))?;
+        }
+
+        return Ok(());
+    }
+
+    fs::create_dir_all(path).context(format!("Failed to create directory {path:?}"))?;
+
+    if let Some(source) = source {
+        fs::write(path.join("init.lua"), source)
+            .context(format!("Failed to write script {path:?}"))?;
+    }
+
+    for referent in instance.children() {
+        let child = dom
+            .get_by_ref(*referent)
+            .context("Model contains a dangling referent")?;
+
+        if !is_plain_file_name(&child.name) {
+            bail!(
+                "Instance {:?} in {path:?} can't be unpacked, its name isn't a plain file name",
+                child.name
+            );
+        }
+
+        unpack_instance(dom, child, &path.join(&child.name))?;
+    }
+
+    Ok(())
+}
+
+/// Instance names come from the model, so anything that isn't a single normal path component
+/// (`..`, separators, absolute paths) could write outside the directory being unpacked into.
+fn is_plain_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+
+    !name.contains(['/', '\\'])
+        && matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+}
+
+fn script_source(instance: &Instance) -> Option<String> {
+    if !matches!(
+        instance.class.as_str(),
+        "ModuleScript" | "Script" | "LocalScript"
+    ) {
+        return None;
+    }
+
+    match instance.properties.get("Source") {
+        Some(Variant::String(source)) => Some(source.to_owned()),
+        Some(Variant::BinaryString(source)) => {
+            Some(String::from_utf8_lossy(source.as_ref()).into_owned())
+        }
+        _ => Some(String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rbx_dom_weak::InstanceBuilder;
+
+    use super::*;
+
+    fn module_script(name: &str, source: &str) -> InstanceBuilder {
+        InstanceBuilder::new("ModuleScript")
+            .with_name(name)
+            .with_property("Source", source)
+    }
+
+    fn packages_model(package: InstanceBuilder) -> WeakDom {
+        WeakDom::new(
+            InstanceBuilder::new("DataModel").with_child(
+                InstanceBuilder::new("Folder")
+                    .with_name("Packages")
+                    .with_child(
+                        InstanceBuilder::new("Folder")
+                            .with_name("_Index")
+                            .with_child(package),
+                    ),
+            ),
+        )
+    }
+
+    #[test]
+    fn unpacks_packages_like_rojo_would_read_them() {
+        let dom = packages_model(
+            InstanceBuilder::new("Folder")
+                .with_name("React-9c8468d8-8a7220fd")
+                .with_child(
+                    module_script("React", "return require(script.ReactHooks)")
+                        .with_child(module_script("ReactHooks", "return {}")),
+                )
+                .with_child(module_script("Foo.roblox", "return 1")),
+        );
+
+        let packages = find_packages_folder(&dom).unwrap();
+        assert_eq!(packages.name, "Packages");
+
+        let unpacked = tempfile::tempdir().unwrap();
+        let path = unpacked.path().join("Packages");
+        unpack_instance(&dom, packages, &path).unwrap();
+
+        let package_path = path.join("_Index/React-9c8468d8-8a7220fd");
+        assert_eq!(
+            fs::read_to_string(package_path.join("React/init.lua")).unwrap(),
+            "return require(script.ReactHooks)"
+        );
+        assert_eq!(
+            fs::read_to_string(package_path.join("React/ReactHooks.lua")).unwrap(),
+            "return {}"
+        );
+        assert_eq!(
+            fs::read_to_string(package_path.join("Foo.roblox.lua")).unwrap(),
+            "return 1"
+        );
+    }
+
+    #[test]
+    fn only_scripts_have_sources() {
+        let script = InstanceBuilder::new("ModuleScript").with_name("Empty");
+        let folder = InstanceBuilder::new("Folder").with_name("Folder");
+        let dom = WeakDom::new(InstanceBuilder::new("Folder").with_children([script, folder]));
+
+        let children = dom
+            .root()
+            .children()
+            .iter()
+            .map(|referent| script_source(dom.get_by_ref(*referent).unwrap()))
+            .collect::<Vec<Option<String>>>();
+
+        assert_eq!(children, vec![Some(String::new()), None]);
+    }
+
+    #[test]
+    fn models_without_an_index_have_no_packages_folder() {
+        let dom = WeakDom::new(
+            InstanceBuilder::new("DataModel")
+                .with_child(InstanceBuilder::new("Folder").with_name("Packages")),
+        );
+
+        assert!(find_packages_folder(&dom).is_none());
+    }
+
+    #[test]
+    fn names_that_escape_the_directory_are_rejected() {
+        for name in [
+            "..",
+            ".",
+            "../Escaped",
+            "/tmp/Escaped",
+            "Nested/Name",
+            "Nested\\Name",
+            "",
+        ] {
+            let dom = packages_model(module_script(name, "return 1"));
+
+            let unpacked = tempfile::tempdir().unwrap();
+            let path = unpacked.path().join("Packages");
+            let packages = find_packages_folder(&dom).unwrap();
+
+            assert!(
+                unpack_instance(&dom, packages, &path).is_err(),
+                "{name:?} was unpacked"
+            );
+        }
+    }
+}